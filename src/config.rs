@@ -0,0 +1,386 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// User-level configuration for spm, read from `$SPM_CONFIG` or `~/.config/spm/config.toml`.
+/// The same tables can also be declared in spm.toml, which take precedence.
+pub struct SpmConfig {
+    /// Settings for self-hosted forges, keyed by host (with port, if any).
+    /// Example:
+    /// ```
+    /// [hosts."gitlab.mycorp.com"]
+    /// kind = "gitlab"
+    /// ```
+    pub hosts: Option<HashMap<String, HostConfig>>,
+    /// Settings for the sqlpkg registry, used by `sqlpkg:` extensions
+    pub sqlpkg: Option<SqlpkgConfig>,
+    /// Settings for the Python package index, used by `pypi:` extensions
+    pub pypi: Option<PypiConfig>,
+    /// Settings for the npm registry, used by `npm:` extensions
+    pub npm: Option<NpmConfig>,
+    /// Settings for S3-compatible buckets, used by `s3://` extensions
+    pub s3: Option<S3Config>,
+    /// Settings for the package index, used to resolve short names like `hello`
+    pub index: Option<IndexConfig>,
+    /// Named package indexes, keyed by name. Example:
+    /// ```
+    /// [registries.corp]
+    /// url = "https://spm.mycorp.com/index/"
+    /// prefixes = ["corp-"]
+    /// ```
+    pub registries: Option<HashMap<String, RegistryConfig>>,
+    /// URL rewrite rules applied to every request, in order. Example:
+    /// ```
+    /// [[mirror]]
+    /// from = "https://github.com/"
+    /// to = "https://artifactory.mycorp.com/github/"
+    /// ```
+    pub mirror: Option<Vec<MirrorConfig>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// The kind of forge that a host runs, which determines the resolver used for it.
+pub enum HostKind {
+    /// Github, or Github Enterprise Server
+    Github,
+    Gitlab,
+    /// Gitea and its forks, like Forgejo (which runs Codeberg)
+    Gitea,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Settings for a single host
+pub struct HostConfig {
+    /// Which forge the host runs
+    pub kind: Option<HostKind>,
+    /// Base web URL of the host, defaults to `https://{host}`
+    pub url: Option<String>,
+    /// Base URL of the host's API, defaults depend on `kind`
+    pub api_url: Option<String>,
+    /// API token sent with every request to this host and its API. For github.com,
+    /// defaults to the `GH_TOKEN` or `GITHUB_TOKEN` env vars. Only read from the user
+    /// config, tokens in spm.toml are ignored.
+    pub token: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Settings for the sqlpkg registry
+pub struct SqlpkgConfig {
+    /// Base URL, or local path, of a sqlpkg registry checkout. Specs are read from
+    /// `{registry}/pkg/{owner}/{name}.json`. Defaults to the `SPM_SQLPKG_REGISTRY`
+    /// env var, then github.com/nalgeon/sqlpkg.
+    pub registry: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Settings for the Python package index
+pub struct PypiConfig {
+    /// Base URL of a package index that serves PyPI's JSON API at `{url}/pypi/...`.
+    /// Defaults to the `SPM_PYPI_URL` env var, then https://pypi.org.
+    pub url: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Settings for the npm registry
+pub struct NpmConfig {
+    /// Base URL of the npm registry. Defaults to the `SPM_NPM_REGISTRY` env var,
+    /// then https://registry.npmjs.org.
+    pub registry: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Settings for S3-compatible buckets
+pub struct S3Config {
+    /// Endpoint of an S3-compatible service, like MinIO, which is addressed with
+    /// path-style URLs. Defaults to the `AWS_ENDPOINT_URL_S3` or `AWS_ENDPOINT_URL`
+    /// env vars, then AWS itself.
+    pub endpoint: Option<String>,
+    /// Region that requests are signed for. Defaults to the `AWS_REGION` or
+    /// `AWS_DEFAULT_REGION` env vars, then the AWS config file, then `us-east-1`.
+    pub region: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Settings for the package index
+pub struct IndexConfig {
+    /// Base URL of a static package index, or a local directory or `file://` URL.
+    /// Relative directories are relative to the config file. Defaults to the `SPM_INDEX`
+    /// env var, relative to the current directory.
+    pub url: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Settings for a named package index
+pub struct RegistryConfig {
+    /// Base URL of a static package index, or a local directory or `file://` URL.
+    /// Relative directories are relative to the file that declares the registry.
+    pub url: String,
+    /// Name prefixes that only this registry (and other registries scoped to them)
+    /// resolves. Registries without prefixes resolve every other name.
+    pub prefixes: Option<Vec<String>>,
+    /// Registries with a higher priority are tried first, defaults to 0
+    #[serde(default)]
+    pub priority: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Which URL spm.lock records for URLs that a mirror rule rewrites
+pub enum MirrorLock {
+    /// The original URL, which is rewritten again on every install
+    #[default]
+    Canonical,
+    /// The rewritten URL
+    Mirror,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A rule that rewrites URLs starting with `from` to start with `to` instead
+pub struct MirrorConfig {
+    pub from: String,
+    pub to: String,
+    /// Which URL spm.lock records, defaults to the canonical one
+    #[serde(default)]
+    pub lock: MirrorLock,
+}
+
+impl SpmConfig {
+    /// Path to the user config file, if a home directory can be found
+    fn user_config_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("SPM_CONFIG") {
+            return Some(path.into());
+        }
+        let config_directory = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_directory.join("spm").join("config.toml"))
+    }
+
+    /// Read the user config file. A missing file is an empty config.
+    pub fn load_user_config() -> Result<SpmConfig> {
+        let path = match SpmConfig::user_config_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(SpmConfig::default()),
+        };
+        SpmConfig::read(&path)
+    }
+
+    /// Read a config file, resolving relative index locations against its directory
+    fn read(path: &Path) -> Result<SpmConfig> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        let mut config: SpmConfig = toml::from_str(&contents)
+            .with_context(|| format!("{} is not valid", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        if let Some(url) = config.index.as_mut().and_then(|index| index.url.as_mut()) {
+            *url = resolve_location(url, base);
+        }
+        for registry in config.registries.iter_mut().flat_map(|r| r.values_mut()) {
+            registry.url = resolve_location(&registry.url, base);
+        }
+        Ok(config)
+    }
+
+    /// Overlay project-level settings from spm.toml, which lives in `base`, on top of this
+    /// config. Host settings are merged field by field, except for `token`, which spm.toml
+    /// can't set. Relative registry locations are resolved against `base`.
+    pub fn merge(
+        &mut self,
+        hosts: Option<HashMap<String, HostConfig>>,
+        registries: Option<HashMap<String, RegistryConfig>>,
+        base: &Path,
+    ) {
+        for (host, project_host) in hosts.into_iter().flatten() {
+            let host_config = self
+                .hosts
+                .get_or_insert_with(HashMap::new)
+                .entry(host)
+                .or_default();
+            host_config.kind = project_host.kind.or(host_config.kind);
+            host_config.url = project_host.url.or(host_config.url.take());
+            host_config.api_url = project_host.api_url.or(host_config.api_url.take());
+        }
+        for (name, mut registry) in registries.into_iter().flatten() {
+            registry.url = resolve_location(&registry.url, base);
+            self.registries
+                .get_or_insert_with(HashMap::new)
+                .insert(name, registry);
+        }
+    }
+
+    /// All configured tokens as (host, token) pairs, including the hosts of any `api_url`.
+    /// Only meaningful for the user config, see [`SpmConfig::merge`].
+    pub fn tokens(&self) -> Vec<(String, String)> {
+        let mut tokens = vec![];
+        for (host, host_config) in self.hosts.iter().flatten() {
+            if let Some(token) = &host_config.token {
+                for url in [&host_config.url, &host_config.api_url]
+                    .into_iter()
+                    .flatten()
+                {
+                    if let Some(url_host) = Url::parse(url).ok().as_ref().and_then(host_key) {
+                        tokens.push((url_host, token.clone()));
+                    }
+                }
+                tokens.push((host.clone(), token.clone()));
+            }
+        }
+        let github_token = std::env::var("GH_TOKEN")
+            .or_else(|_| std::env::var("GITHUB_TOKEN"))
+            .ok()
+            .filter(|token| !token.is_empty());
+        if let Some(token) = github_token {
+            let mut hosts = vec!["github.com".to_owned(), "api.github.com".to_owned()];
+            for name in ["SPM_GITHUB_URL", "SPM_GITHUB_API_URL"] {
+                if let Some(host) = std::env::var(name)
+                    .ok()
+                    .and_then(|url| Url::parse(&url).ok())
+                    .as_ref()
+                    .and_then(host_key)
+                {
+                    hosts.push(host);
+                }
+            }
+            for host in hosts {
+                // tokens from the hosts config win over the environment
+                if !tokens.iter().any(|(token_host, _)| *token_host == host) {
+                    tokens.push((host, token.clone()));
+                }
+            }
+        }
+        tokens
+    }
+
+    /// Settings for the host of the given URL, matching `host:port` before `host`
+    pub fn host(&self, url: &Url) -> Option<&HostConfig> {
+        let hosts = self.hosts.as_ref()?;
+        let host = url.host_str()?;
+        url.port()
+            .and_then(|port| hosts.get(&format!("{host}:{port}")))
+            .or_else(|| hosts.get(host))
+    }
+}
+
+/// A package index location relative to `base`. URLs and absolute paths are kept as is.
+fn resolve_location(location: &str, base: &Path) -> String {
+    match Url::parse(location) {
+        Ok(url) if url.has_host() || url.scheme() == "file" => location.to_owned(),
+        _ => base.join(location).display().to_string(),
+    }
+}
+
+/// The host of a URL, with the port if it isn't the default one
+pub fn host_key(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+    Some(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens() {
+        crate::test_server::isolate();
+        let config: SpmConfig = toml::from_str(
+            r#"
+            [hosts."github.mycorp.com"]
+            kind = "github"
+            api_url = "https://api.github.mycorp.com:8443"
+            token = "abc"
+
+            [hosts."gitlab.mycorp.com"]
+            kind = "gitlab"
+            "#,
+        )
+        .unwrap();
+        let tokens = config.tokens();
+        assert!(tokens.contains(&("github.mycorp.com".to_owned(), "abc".to_owned())));
+        assert!(tokens.contains(&("api.github.mycorp.com:8443".to_owned(), "abc".to_owned())));
+        assert!(!tokens.iter().any(|(host, _)| host == "gitlab.mycorp.com"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut config: SpmConfig = toml::from_str(
+            r#"
+            [hosts."git.mycorp.com"]
+            kind = "gitea"
+            api_url = "https://git.mycorp.com/api/v1"
+            token = "abc"
+            "#,
+        )
+        .unwrap();
+        let spm_toml: SpmConfig = toml::from_str(
+            r#"
+            [hosts."git.mycorp.com"]
+            url = "https://git.mycorp.com:3000"
+            token = "def"
+
+            [hosts."gitlab.example.com"]
+            kind = "gitlab"
+            token = "ghi"
+            "#,
+        )
+        .unwrap();
+        config.merge(spm_toml.hosts, None, Path::new("/project"));
+        let hosts = config.hosts.as_ref().unwrap();
+        assert_eq!(
+            hosts["git.mycorp.com"],
+            HostConfig {
+                kind: Some(HostKind::Gitea),
+                url: Some("https://git.mycorp.com:3000".to_owned()),
+                api_url: Some("https://git.mycorp.com/api/v1".to_owned()),
+                token: Some("abc".to_owned()),
+            }
+        );
+        assert_eq!(hosts["gitlab.example.com"].kind, Some(HostKind::Gitlab));
+        assert_eq!(hosts["gitlab.example.com"].token, None);
+    }
+
+    #[test]
+    fn test_relative_locations() {
+        let directory =
+            std::env::temp_dir().join(format!("spm-test-config-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [index]
+            url = "index"
+
+            [registries.corp]
+            url = "https://spm.mycorp.com/index/"
+            "#,
+        )
+        .unwrap();
+        let mut config = SpmConfig::read(&path).unwrap();
+        assert_eq!(
+            config.index.as_ref().unwrap().url,
+            Some(directory.join("index").display().to_string())
+        );
+
+        let spm_toml: SpmConfig = toml::from_str("[registries.local]\nurl = '../index'").unwrap();
+        config.merge(None, spm_toml.registries, &directory.join("project"));
+        let registries = config.registries.unwrap();
+        assert_eq!(registries["corp"].url, "https://spm.mycorp.com/index/");
+        assert_eq!(
+            registries["local"].url,
+            directory
+                .join("project")
+                .join("../index")
+                .display()
+                .to_string()
+        );
+    }
+}
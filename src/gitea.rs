@@ -0,0 +1,289 @@
+use crate::https::HttpsExtension;
+use crate::spm::{
+    fetch_spm_json, is_prerelease_tag, PackageResolver, PackageVersion, SpmLockExtension,
+    SpmTomlExtensionDefinition,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Deserialize)]
+struct GiteaRelease {
+    tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    assets: Vec<GiteaReleaseAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GiteaReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// A SQLite extension project that's published and distributed with the releases of a
+/// Gitea-compatible forge, like Codeberg or a self-hosted Forgejo.
+pub struct GiteaReleasePackage {
+    /// Origin the repo was referenced by, ex `https://codeberg.org`, which its spm.toml key is
+    /// built from. Differs from the web URL that requests go to when the host's `url` is
+    /// overridden in the `hosts` config.
+    origin: String,
+    /// Base URL of the Gitea REST API, ex `https://codeberg.org/api/v1`
+    api_url: String,
+    owner: String,
+    repo: String,
+    version: Option<String>,
+    prerelease: bool,
+}
+
+impl GiteaReleasePackage {
+    /// `base_url` is the web URL of the forge, and `api_url` defaults to `{base_url}/api/v1`.
+    /// `path` is `owner/repo`, optionally suffixed with `@version`.
+    pub fn parse(
+        base_url: &str,
+        api_url: Option<&str>,
+        path: &str,
+        prerelease: bool,
+    ) -> Result<GiteaReleasePackage> {
+        let base_url = base_url.trim_end_matches('/').to_owned();
+        let api_url = api_url.map_or_else(
+            || format!("{base_url}/api/v1"),
+            |api_url| api_url.trim_end_matches('/').to_owned(),
+        );
+        let mut parts = path.trim_matches('/').split('/');
+        let owner = parts
+            .next()
+            .filter(|owner| !owner.is_empty())
+            .ok_or_else(|| anyhow!("gitea owner name required"))?
+            .to_owned();
+        let repo = parts
+            .next()
+            .ok_or_else(|| anyhow!("gitea repo name required"))?;
+        let (repo, version) = match repo.split_once('@') {
+            Some((repo, version)) => (repo, Some(version.to_owned())),
+            None => (repo, None),
+        };
+        Ok(GiteaReleasePackage {
+            origin: base_url,
+            api_url,
+            owner,
+            repo: repo.trim_end_matches(".git").to_owned(),
+            version,
+            prerelease,
+        })
+    }
+
+    /// Use `origin` for the spm.toml key, instead of the base web URL given to `parse`
+    pub fn with_origin(mut self, origin: &str) -> GiteaReleasePackage {
+        self.origin = origin.trim_end_matches('/').to_owned();
+        self
+    }
+
+    fn repo_api_url(&self) -> String {
+        format!("{}/repos/{}/{}", self.api_url, self.owner, self.repo)
+    }
+}
+
+impl PackageResolver for GiteaReleasePackage {
+    fn version_from_reference(&self) -> Result<String> {
+        match &self.version {
+            Some(v) => Ok(v.to_owned()),
+            None => self.latest_version(),
+        }
+    }
+    fn toml_name(&self) -> String {
+        format!("{}/{}/{}", self.origin, self.owner, self.repo)
+    }
+    fn latest_version(&self) -> Result<String> {
+        if self.prerelease {
+            let url = format!("{}/releases?limit=10", self.repo_api_url());
+            let releases: Vec<GiteaRelease> = crate::http::http_get(url.as_str())?
+                .call()
+                .with_context(|| format!("call to {url} failed"))?
+                .into_json()
+                .with_context(|| format!("request did not return proper JSON: {url}"))?;
+            return releases
+                .into_iter()
+                .find(|release| !release.draft)
+                .map(|release| release.tag_name)
+                .ok_or_else(|| anyhow!("No releases found for {}", self.toml_name()));
+        }
+        // the latest release excludes drafts and pre-releases
+        let url = format!("{}/releases/latest", self.repo_api_url());
+        let release: GiteaRelease = crate::http::http_get(url.as_str())?
+            .call()
+            .with_context(|| format!("call to {url} failed"))?
+            .into_json()
+            .with_context(|| format!("request did not return proper JSON: {url}"))?;
+        Ok(release.tag_name)
+    }
+    fn list_versions(&self) -> Result<Vec<PackageVersion>> {
+        let url = format!("{}/releases?limit=50", self.repo_api_url());
+        let releases: Vec<GiteaRelease> = crate::http::http_get_all_pages(&url)?;
+        Ok(releases
+            .into_iter()
+            .filter(|release| !release.draft)
+            .map(|release| {
+                PackageVersion::from_assets(
+                    release.prerelease || is_prerelease_tag(&release.tag_name),
+                    release.assets.iter().map(|asset| asset.name.as_str()),
+                    release.tag_name.clone(),
+                )
+            })
+            .collect())
+    }
+    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
+        let version = definition.required_version(&self.toml_name())?;
+        let artifacts = definition.artifacts();
+
+        let url = format!("{}/releases/tags/{version}", self.repo_api_url());
+        let release: GiteaRelease = crate::http::http_get(url.as_str())?
+            .call()
+            .with_context(|| format!("call to {url} failed"))?
+            .into_json()
+            .with_context(|| format!("request did not return proper JSON: {url}"))?;
+        let asset_url = |name: &str| {
+            release
+                .assets
+                .iter()
+                .find(|asset| asset.name == name)
+                .map(|asset| asset.browser_download_url.clone())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Release {version} of {} has no {name} asset",
+                        self.toml_name()
+                    )
+                })
+        };
+
+        let resolved_spm_json = asset_url("spm.json")?;
+
+        let url = resolved_spm_json.as_str();
+        let (spm_json, integrity) = fetch_spm_json(url)?;
+
+        let resolved_assets = spm_json
+            .loadable
+            .iter()
+            .chain(spm_json.static_.iter().flatten())
+            .map(|platform| {
+                Ok((
+                    platform.asset_name.clone(),
+                    asset_url(&platform.asset_name)?,
+                ))
+            })
+            .collect::<Result<BTreeMap<String, String>>>()?;
+
+        Ok(SpmLockExtension::Https(HttpsExtension {
+            version,
+            artifacts,
+            resolved_spm_json: Some(resolved_spm_json),
+            resolved_assets,
+            integrity,
+            spm_json,
+            inferred: false,
+            loadables_only: false,
+            registry: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_releases() {
+        let spm_json = serde_json::json!({
+          "version": 0,
+          "description": "",
+          "loadable": [{
+            "os": "linux",
+            "cpu": "x86_64",
+            "asset_name": "foo-linux-x86_64.tar.gz",
+            "asset_sha256": "",
+            "asset_md5": ""
+          }]
+        });
+        let release = |tag: &str, draft: bool| {
+            serde_json::json!({
+              "tag_name": tag,
+              "draft": draft,
+              "assets": [
+                {"name": "spm.json", "browser_download_url": format!("$SERVER/owner/foo/releases/download/{tag}/spm.json")},
+                {"name": "foo-linux-x86_64.tar.gz", "browser_download_url": format!("$SERVER/owner/foo/releases/download/{tag}/foo-linux-x86_64.tar.gz")}
+              ]
+            })
+        };
+        let server = TestServer::start(vec![
+            (
+                "/api/v1/repos/owner/foo/releases/latest".to_owned(),
+                release("v0.1.0", false).to_string().into_bytes(),
+            ),
+            (
+                "/api/v1/repos/owner/foo/releases?limit=10".to_owned(),
+                serde_json::json!([release("v0.3.0", true), release("v0.2.0-alpha.1", false)])
+                    .to_string()
+                    .into_bytes(),
+            ),
+            (
+                "/api/v1/repos/owner/foo/releases/tags/v0.1.0".to_owned(),
+                release("v0.1.0", false).to_string().into_bytes(),
+            ),
+            (
+                "/owner/foo/releases/download/v0.1.0/spm.json".to_owned(),
+                spm_json.to_string().into_bytes(),
+            ),
+        ]);
+
+        let package =
+            GiteaReleasePackage::parse(&server.url(""), None, "owner/foo", false).unwrap();
+        assert_eq!(package.toml_name(), server.url("/owner/foo"));
+        assert_eq!(package.latest_version().unwrap(), "v0.1.0");
+        let package = GiteaReleasePackage::parse(&server.url(""), None, "owner/foo", true).unwrap();
+        assert_eq!(package.latest_version().unwrap(), "v0.2.0-alpha.1");
+
+        match package
+            .generate_lock(&SpmTomlExtensionDefinition::Version("v0.1.0".to_owned()))
+            .unwrap()
+        {
+            SpmLockExtension::Https(extension) => assert_eq!(
+                extension.resolved_assets.get("foo-linux-x86_64.tar.gz"),
+                Some(&server.url("/owner/foo/releases/download/v0.1.0/foo-linux-x86_64.tar.gz"))
+            ),
+            _ => panic!("expected an HTTPS lock entry"),
+        }
+    }
+
+    #[test]
+    fn test_host_url_override() {
+        let server = TestServer::start(vec![(
+            "/api/v1/repos/owner/foo/releases/latest".to_owned(),
+            br#"{"tag_name": "v0.1.0", "draft": false, "assets": []}"#.to_vec(),
+        )]);
+        let config: crate::config::SpmConfig = toml::from_str(&format!(
+            r#"
+            [hosts."git.corp"]
+            kind = "gitea"
+            url = "{}"
+            "#,
+            server.url("")
+        ))
+        .unwrap();
+
+        // requests go to the configured URL, but spm.toml keeps the host that was typed
+        let resolver =
+            crate::spm::determine_package_resolver("https://git.corp/owner/foo", false, &config)
+                .unwrap();
+        assert_eq!(resolver.toml_name(), "https://git.corp/owner/foo");
+        assert_eq!(resolver.latest_version().unwrap(), "v0.1.0");
+        let resolver =
+            crate::spm::determine_package_resolver(&resolver.toml_name(), false, &config).unwrap();
+        assert_eq!(resolver.toml_name(), "https://git.corp/owner/foo");
+        assert_eq!(resolver.latest_version().unwrap(), "v0.1.0");
+    }
+}
@@ -0,0 +1,340 @@
+use crate::https::HttpsExtension;
+use crate::spm::{
+    fetch_spm_json, is_prerelease_tag, PackageResolver, PackageVersion, SpmLockExtension,
+    SpmTomlExtensionDefinition,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Deserialize)]
+struct GitlabRelease {
+    tag_name: String,
+    #[serde(default)]
+    upcoming_release: bool,
+    assets: GitlabReleaseAssets,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitlabReleaseAssets {
+    links: Vec<GitlabReleaseLink>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitlabReleaseLink {
+    name: String,
+    url: String,
+    direct_asset_url: Option<String>,
+}
+
+impl GitlabReleaseLink {
+    fn download_url(&self) -> &str {
+        self.direct_asset_url.as_deref().unwrap_or(&self.url)
+    }
+}
+
+/// A SQLite extension project that's published and distributed with GitLab Releases,
+/// with spm.json and every asset attached as release links.
+pub struct GitlabReleasePackage {
+    /// Origin the project was referenced by, ex `https://gitlab.com`, which its spm.toml key is
+    /// built from. Differs from the web URL that requests go to when the host's `url` is
+    /// overridden in the `hosts` config.
+    origin: String,
+    /// Base URL of the GitLab REST API, ex `https://gitlab.com/api/v4`
+    api_url: String,
+    /// Full path of the project, including any subgroups, ex `group/subgroup/project`
+    project: String,
+    version: Option<String>,
+    prerelease: bool,
+}
+
+impl GitlabReleasePackage {
+    /// `base_url` is the web URL of the GitLab instance, and `api_url` defaults to
+    /// `{base_url}/api/v4`. `path` is the project path, optionally suffixed with `@version`.
+    pub fn parse(
+        base_url: &str,
+        api_url: Option<&str>,
+        path: &str,
+        prerelease: bool,
+    ) -> Result<GitlabReleasePackage> {
+        let base_url = base_url.trim_end_matches('/').to_owned();
+        let api_url = api_url.map_or_else(
+            || format!("{base_url}/api/v4"),
+            |api_url| api_url.trim_end_matches('/').to_owned(),
+        );
+        // GitLab web URLs put everything after the project path behind a `/-/` segment
+        let path = path.split("/-/").next().unwrap_or(path).trim_matches('/');
+        let (project, version) = match path.rsplit_once('@') {
+            Some((project, version)) => (project, Some(version.to_owned())),
+            None => (path, None),
+        };
+        let project = project.trim_end_matches(".git");
+        if project.split('/').filter(|part| !part.is_empty()).count() < 2 {
+            return Err(anyhow!(
+                "gitlab project path must include a group and a project name: {path}"
+            ));
+        }
+        Ok(GitlabReleasePackage {
+            origin: base_url,
+            api_url,
+            project: project.to_owned(),
+            version,
+            prerelease,
+        })
+    }
+
+    /// Use `origin` for the spm.toml key, instead of the base web URL given to `parse`
+    pub fn with_origin(mut self, origin: &str) -> GitlabReleasePackage {
+        self.origin = origin.trim_end_matches('/').to_owned();
+        self
+    }
+
+    fn project_api_url(&self) -> String {
+        format!(
+            "{}/projects/{}",
+            self.api_url,
+            url::form_urlencoded::byte_serialize(self.project.as_bytes()).collect::<String>()
+        )
+    }
+
+    fn fetch_release(&self, version: &str) -> Result<GitlabRelease> {
+        let url = format!(
+            "{}/releases/{}",
+            self.project_api_url(),
+            url::form_urlencoded::byte_serialize(version.as_bytes()).collect::<String>()
+        );
+        crate::http::http_get(url.as_str())?
+            .call()
+            .with_context(|| format!("call to {url} failed"))?
+            .into_json()
+            .with_context(|| format!("request did not return proper JSON: {url}"))
+    }
+}
+
+impl PackageResolver for GitlabReleasePackage {
+    fn version_from_reference(&self) -> Result<String> {
+        match &self.version {
+            Some(v) => Ok(v.to_owned()),
+            None => self.latest_version(),
+        }
+    }
+    fn toml_name(&self) -> String {
+        format!("{}/{}", self.origin, self.project)
+    }
+    fn latest_version(&self) -> Result<String> {
+        // releases are sorted by release date, newest first
+        let url = format!("{}/releases?per_page=100", self.project_api_url());
+        let releases: Vec<GitlabRelease> = crate::http::http_get(url.as_str())?
+            .call()
+            .with_context(|| format!("call to {url} failed"))?
+            .into_json()
+            .with_context(|| format!("request did not return proper JSON: {url}"))?;
+        releases
+            .into_iter()
+            .filter(|release| !release.upcoming_release)
+            .find(|release| self.prerelease || !is_prerelease_tag(&release.tag_name))
+            .map(|release| release.tag_name)
+            .ok_or_else(|| anyhow!("No releases found for {}", self.toml_name()))
+    }
+    fn list_versions(&self) -> Result<Vec<PackageVersion>> {
+        let url = format!("{}/releases?per_page=100", self.project_api_url());
+        let releases: Vec<GitlabRelease> = crate::http::http_get_all_pages(&url)?;
+        Ok(releases
+            .into_iter()
+            .filter(|release| !release.upcoming_release)
+            .map(|release| {
+                PackageVersion::from_assets(
+                    is_prerelease_tag(&release.tag_name),
+                    release.assets.links.iter().map(|link| link.name.as_str()),
+                    release.tag_name.clone(),
+                )
+            })
+            .collect())
+    }
+    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
+        let version = definition.required_version(&self.toml_name())?;
+        let artifacts = definition.artifacts();
+        let release = self.fetch_release(&version)?;
+
+        let resolved_spm_json = release
+            .assets
+            .links
+            .iter()
+            .find(|link| link.name == "spm.json")
+            .ok_or_else(|| {
+                anyhow!(
+                    "Release {version} of {} has no spm.json release link",
+                    self.toml_name()
+                )
+            })?
+            .download_url()
+            .to_owned();
+
+        let url = resolved_spm_json.as_str();
+        let (spm_json, integrity) = fetch_spm_json(url)?;
+
+        let resolved_assets = spm_json
+            .loadable
+            .iter()
+            .chain(spm_json.static_.iter().flatten())
+            .map(|platform| {
+                let link = release
+                    .assets
+                    .links
+                    .iter()
+                    .find(|link| link.name == platform.asset_name)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Release {version} has no release link for {}",
+                            platform.asset_name
+                        )
+                    })?;
+                Ok((platform.asset_name.clone(), link.download_url().to_owned()))
+            })
+            .collect::<Result<BTreeMap<String, String>>>()?;
+
+        Ok(SpmLockExtension::Https(HttpsExtension {
+            version,
+            artifacts,
+            resolved_spm_json: Some(resolved_spm_json),
+            resolved_assets,
+            integrity,
+            spm_json,
+            inferred: false,
+            loadables_only: false,
+            registry: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[test]
+    fn test_parse() {
+        let p = GitlabReleasePackage::parse(
+            "https://gitlab.com",
+            None,
+            "group/subgroup/project@v0.1.0",
+            false,
+        )
+        .unwrap();
+        assert_eq!(p.toml_name(), "https://gitlab.com/group/subgroup/project");
+        assert_eq!(p.version, Some("v0.1.0".to_owned()));
+        assert_eq!(
+            p.project_api_url(),
+            "https://gitlab.com/api/v4/projects/group%2Fsubgroup%2Fproject"
+        );
+
+        let p = GitlabReleasePackage::parse(
+            "https://gitlab.com/",
+            None,
+            "/group/project/-/releases",
+            false,
+        )
+        .unwrap();
+        assert_eq!(p.toml_name(), "https://gitlab.com/group/project");
+        assert_eq!(p.version, None);
+
+        assert!(GitlabReleasePackage::parse("https://gitlab.com", None, "project", false).is_err());
+    }
+
+    #[test]
+    fn test_releases() {
+        let spm_json = serde_json::json!({
+          "version": 0,
+          "description": "",
+          "loadable": [{
+            "os": "linux",
+            "cpu": "x86_64",
+            "asset_name": "foo-linux-x86_64.tar.gz",
+            "asset_sha256": "",
+            "asset_md5": ""
+          }]
+        });
+        let release = |tag: &str| {
+            serde_json::json!({
+              "tag_name": tag,
+              "assets": {"links": [
+                {"name": "spm.json", "url": "$SERVER/files/spm.json"},
+                {
+                  "name": "foo-linux-x86_64.tar.gz",
+                  "url": "$SERVER/unused",
+                  "direct_asset_url": "$SERVER/files/foo-linux-x86_64.tar.gz"
+                }
+              ]}
+            })
+        };
+        let server = TestServer::start(vec![
+            (
+                "/api/v4/projects/g%2Fsub%2Ffoo/releases?per_page=100".to_owned(),
+                serde_json::json!([release("v0.2.0-alpha.1"), release("v0.1.0")])
+                    .to_string()
+                    .into_bytes(),
+            ),
+            (
+                "/api/v4/projects/g%2Fsub%2Ffoo/releases/v0.1.0".to_owned(),
+                release("v0.1.0").to_string().into_bytes(),
+            ),
+            (
+                "/files/spm.json".to_owned(),
+                spm_json.to_string().into_bytes(),
+            ),
+        ]);
+
+        let package =
+            GitlabReleasePackage::parse(&server.url(""), None, "g/sub/foo", false).unwrap();
+        assert_eq!(package.latest_version().unwrap(), "v0.1.0");
+        let package =
+            GitlabReleasePackage::parse(&server.url(""), None, "g/sub/foo", true).unwrap();
+        assert_eq!(package.latest_version().unwrap(), "v0.2.0-alpha.1");
+
+        let lock = package
+            .generate_lock(&SpmTomlExtensionDefinition::Version("v0.1.0".to_owned()))
+            .unwrap();
+        match lock {
+            SpmLockExtension::Https(extension) => {
+                assert_eq!(
+                    extension.resolved_spm_json,
+                    Some(server.url("/files/spm.json"))
+                );
+                assert_eq!(
+                    extension.resolved_assets.get("foo-linux-x86_64.tar.gz"),
+                    Some(&server.url("/files/foo-linux-x86_64.tar.gz"))
+                );
+            }
+            _ => panic!("expected an HTTPS lock entry"),
+        }
+    }
+
+    #[test]
+    fn test_host_url_override() {
+        let server = TestServer::start(vec![(
+            "/api/v4/projects/g%2Ffoo/releases?per_page=100".to_owned(),
+            br#"[{"tag_name": "v0.1.0", "assets": {"links": []}}]"#.to_vec(),
+        )]);
+        let config: crate::config::SpmConfig = toml::from_str(&format!(
+            r#"
+            [hosts."git.corp"]
+            kind = "gitlab"
+            url = "{}"
+            "#,
+            server.url("")
+        ))
+        .unwrap();
+
+        // requests go to the configured URL, but spm.toml keeps the host that was typed
+        let resolver =
+            crate::spm::determine_package_resolver("https://git.corp/g/foo", false, &config)
+                .unwrap();
+        assert_eq!(resolver.toml_name(), "https://git.corp/g/foo");
+        assert_eq!(resolver.latest_version().unwrap(), "v0.1.0");
+        let resolver =
+            crate::spm::determine_package_resolver(&resolver.toml_name(), false, &config).unwrap();
+        assert_eq!(resolver.toml_name(), "https://git.corp/g/foo");
+        assert_eq!(resolver.latest_version().unwrap(), "v0.1.0");
+    }
+}
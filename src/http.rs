@@ -1,9 +1,173 @@
+use crate::config::{host_key, MirrorConfig, MirrorLock};
+
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use url::Url;
+
 const USER_AGENT: &str = concat!(
     "sqlite-package-manager/",
     env!("CARGO_PKG_VERSION"),
     " (https://github.com/asg017/sqlite-package-manager)"
 );
 
-pub fn http_get(url: &str) -> ureq::Request {
-    ureq::get(url).set("User-Agent", USER_AGENT)
+/// API tokens to send with requests, keyed by host (with port, if any)
+static TOKENS: RwLock<Vec<(String, String)>> = RwLock::new(Vec::new());
+
+/// Replace the tokens sent with requests. Each entry is a (host, token) pair.
+pub fn set_tokens(tokens: Vec<(String, String)>) {
+    *TOKENS.write().unwrap() = tokens;
+}
+
+/// Whether network requests are forbidden, with `--offline` or `--frozen`
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Forbid (or allow again) every network request
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Mirror rules that requests are rewritten with, in order
+static MIRRORS: RwLock<Vec<MirrorConfig>> = RwLock::new(Vec::new());
+
+/// Replace the mirror rules that requests are rewritten with
+pub fn set_mirrors(mirrors: Vec<MirrorConfig>) {
+    *MIRRORS.write().unwrap() = mirrors;
+}
+
+/// Rewrite a URL with the first rule whose `from` it starts with. With `lock`, only
+/// rules that record the mirror URL in spm.lock apply.
+fn rewrite_url(url: &str, mirrors: &[MirrorConfig], lock: bool) -> Option<String> {
+    let mirror = mirrors
+        .iter()
+        .find(|mirror| url.starts_with(&mirror.from))?;
+    if lock && mirror.lock != MirrorLock::Mirror {
+        return None;
+    }
+    Some(format!("{}{}", mirror.to, &url[mirror.from.len()..]))
+}
+
+/// The URL that a request to the given URL is sent to
+pub fn mirror_url(url: &str) -> String {
+    rewrite_url(url, &MIRRORS.read().unwrap(), false).unwrap_or_else(|| url.to_owned())
+}
+
+/// The URL that spm.lock records for the given URL
+pub fn lock_url(url: &str) -> String {
+    rewrite_url(url, &MIRRORS.read().unwrap(), true).unwrap_or_else(|| url.to_owned())
+}
+
+/// The token configured for the host of the given URL, if any
+fn token_for(url: &str) -> Option<String> {
+    let host = host_key(&Url::parse(url).ok()?)?;
+    TOKENS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(token_host, _)| *token_host == host)
+        .map(|(_, token)| token.clone())
+}
+
+/// Whether requests to the given URL will be authenticated
+pub fn has_token(url: &str) -> bool {
+    token_for(&mirror_url(url)).is_some()
+}
+
+/// A GET request to the given URL, after mirror rules. Fails when offline.
+pub fn http_get(url: &str) -> Result<ureq::Request> {
+    if OFFLINE.load(Ordering::Relaxed) {
+        return Err(anyhow!(
+            "Cannot fetch {url}, because network access is disabled with --offline or --frozen"
+        ));
+    }
+    let url = mirror_url(url);
+    let request = ureq::get(&url).set("User-Agent", USER_AGENT);
+    Ok(match token_for(&url) {
+        Some(token) => request.set("Authorization", &format!("Bearer {token}")),
+        None => request,
+    })
+}
+
+/// Fetch every page of a paginated JSON array, following `Link: <...>; rel="next"` headers
+pub fn http_get_all_pages<T: DeserializeOwned>(url: &str) -> Result<Vec<T>> {
+    let mut items = vec![];
+    let mut next = Some(url.to_owned());
+    while let Some(url) = next {
+        let response = http_get(&url)?
+            .call()
+            .with_context(|| format!("call to {url} failed"))?;
+        next = response.header("Link").and_then(next_link);
+        let page: Vec<T> = response
+            .into_json()
+            .with_context(|| format!("request did not return proper JSON: {url}"))?;
+        items.extend(page);
+    }
+    Ok(items)
+}
+
+/// The `rel="next"` URL of a `Link` header, if any
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == r#"rel="next""#)
+            .then(|| {
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_owned()
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_url() {
+        let config: crate::config::SpmConfig = toml::from_str(
+            r#"
+            [[mirror]]
+            from = "https://github.com/"
+            to = "https://artifactory.mycorp.com/github/"
+
+            [[mirror]]
+            from = "https://"
+            to = "https://proxy.mycorp.com/"
+            lock = "mirror"
+            "#,
+        )
+        .unwrap();
+        let mirrors = config.mirror.unwrap();
+        let url = "https://github.com/asg017/sqlite-vec/releases/download/v0.1.0/spm.json";
+        assert_eq!(
+            rewrite_url(url, &mirrors, false).unwrap(),
+            "https://artifactory.mycorp.com/github/asg017/sqlite-vec/releases/download/v0.1.0/spm.json"
+        );
+        assert_eq!(rewrite_url(url, &mirrors, true), None);
+        let url = "https://registry.npmjs.org/sqlite-vec";
+        assert_eq!(
+            rewrite_url(url, &mirrors, true).unwrap(),
+            "https://proxy.mycorp.com/registry.npmjs.org/sqlite-vec"
+        );
+        assert_eq!(rewrite_url("http://localhost/", &mirrors, false), None);
+    }
+
+    #[test]
+    fn test_next_link() {
+        assert_eq!(
+            next_link(
+                r#"<https://api.github.com/repositories/1/releases?page=3>; rel="next", <https://api.github.com/repositories/1/releases?page=5>; rel="last""#
+            )
+            .unwrap(),
+            "https://api.github.com/repositories/1/releases?page=3"
+        );
+        assert_eq!(
+            next_link(r#"<https://api.github.com/repositories/1/releases?page=1>; rel="prev""#),
+            None
+        );
+    }
 }
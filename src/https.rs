@@ -0,0 +1,410 @@
+use crate::spm::{
+    download_asset, fetch_spm_json, find_loadable_platform, infer_platform, PackageResolver,
+    Platform, Project, SpmLockExtension, SpmPackageJson, SpmPackageJsonPlatform,
+    SpmTomlExtensionDefinition, SpmTomlUrlPlatform,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use url::Url;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// A SQLite extension whose spm.json and assets are hosted on a plain HTTP(S) server.
+pub struct HttpsExtension {
+    /// The version of the extension, the directory that spm.json lives in. Empty for
+    /// extensions defined inline in spm.toml.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub version: String,
+    /// List of release asset names associated with the release
+    pub artifacts: Option<Vec<String>>,
+    /// Full URL to the spm.json manifest for this version. None for extensions defined
+    /// inline in spm.toml, which have no manifest.
+    #[serde(
+        rename = "resolved_spm_json",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub resolved_spm_json: Option<String>,
+    /// Full URLs of every asset listed in spm.json, keyed by asset name
+    #[serde(rename = "resolved_assets")]
+    pub resolved_assets: BTreeMap<String, String>,
+    /// Subresource Integrity hash of the fetched spm.json, ex `sha256-<base64>`. Empty
+    /// for extensions without a fetched spm.json, or in older lockfiles.
+    pub integrity: String,
+    /// The contents of spm.json for this version
+    #[serde(rename = "spm_json")]
+    pub spm_json: SpmPackageJson,
+    /// Whether spm_json was inferred from another package format, because the
+    /// extension doesn't publish a spm.json
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub inferred: bool,
+    /// Whether only the loadable libraries inside each asset are installed, for package
+    /// formats like npm tarballs and Python wheels that also contain sources and metadata
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub loadables_only: bool,
+    /// Name of the registry that resolved the extension, for extensions resolved by
+    /// short name through a package index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+}
+
+impl HttpsExtension {
+    /// Download the platform's asset from the URL recorded in `resolved_assets`, which
+    /// spm.toml, a forge or a package index resolved, and check it against spm.json
+    pub(crate) fn download_platform(&self, platform: Platform, project: &Project) -> Result<()> {
+        let platform = find_loadable_platform(&self.spm_json, platform)?;
+        let url = self
+            .resolved_assets
+            .get(&platform.asset_name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "No resolved URL for {} in spm.lock, try re-running `spm install`",
+                    platform.asset_name
+                )
+            })?;
+        download_asset(url, platform, &self.artifacts, self.loadables_only, project)
+    }
+}
+
+/// Resolves extensions published as `{base}/{version}/spm.json` on any HTTP(S) server,
+/// with assets stored next to spm.json.
+///
+/// Examples:
+///```
+/// "https://artifacts.example.com/sqlite-foo/v1.2.0/spm.json"
+/// "https://artifacts.example.com/sqlite-foo@v1.2.0"
+/// "https://artifacts.example.com/sqlite-foo"
+/// ```
+pub struct HttpsPackage {
+    base: String,
+    version: Option<String>,
+}
+
+impl HttpsPackage {
+    pub fn parse(url: &Url) -> Result<HttpsPackage> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("unsupported URL scheme for package: {url}"));
+        }
+        let mut url = url.clone();
+        url.set_query(None);
+        url.set_fragment(None);
+        let url = url.as_str().trim_end_matches('/');
+
+        if let Some(rest) = url.strip_suffix("/spm.json") {
+            let (base, version) = rest
+                .rsplit_once('/')
+                .ok_or_else(|| anyhow!("expected a version directory before spm.json: {url}"))?;
+            return Ok(HttpsPackage {
+                base: base.to_owned(),
+                version: Some(version.to_owned()),
+            });
+        }
+        match url.rsplit_once('@') {
+            Some((base, version)) if !version.contains('/') => Ok(HttpsPackage {
+                base: base.to_owned(),
+                version: Some(version.to_owned()),
+            }),
+            _ => Ok(HttpsPackage {
+                base: url.to_owned(),
+                version: None,
+            }),
+        }
+    }
+
+    fn spm_json_url(&self, version: &str) -> String {
+        format!("{}/{version}/spm.json", self.base)
+    }
+}
+
+impl PackageResolver for HttpsPackage {
+    fn version_from_reference(&self) -> Result<String> {
+        match &self.version {
+            Some(v) => Ok(v.to_owned()),
+            None => self.latest_version(),
+        }
+    }
+    fn toml_name(&self) -> String {
+        self.base.clone()
+    }
+    fn latest_version(&self) -> Result<String> {
+        Err(anyhow!(
+            "Cannot determine the latest version of {}, specify one like {}",
+            self.base,
+            self.spm_json_url("v1.2.3")
+        ))
+    }
+    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
+        let version = definition.required_version(&self.base)?;
+        let artifacts = definition.artifacts();
+        let resolved_spm_json = self.spm_json_url(&version);
+
+        let url = resolved_spm_json.as_str();
+        let (spm_json, integrity) = fetch_spm_json(url)?;
+
+        let base = Url::parse(url)?;
+        let resolved_assets = spm_json
+            .loadable
+            .iter()
+            .chain(spm_json.static_.iter().flatten())
+            .map(|platform| {
+                let asset_url = base.join(&platform.asset_name).with_context(|| {
+                    format!("Invalid asset name in {url}: {}", platform.asset_name)
+                })?;
+                Ok((platform.asset_name.clone(), asset_url.to_string()))
+            })
+            .collect::<Result<BTreeMap<String, String>>>()?;
+
+        Ok(SpmLockExtension::Https(HttpsExtension {
+            version,
+            artifacts,
+            resolved_spm_json: Some(resolved_spm_json),
+            resolved_assets,
+            integrity,
+            spm_json,
+            inferred: false,
+            loadables_only: false,
+            registry: None,
+        }))
+    }
+}
+
+/// Resolves extensions defined inline in spm.toml by URL and sha256, for artifacts
+/// that aren't published with a spm.json.
+///
+/// Examples:
+///```
+/// "vec" = { url = "https://example.com/vec0-linux-x86_64.tar.gz", sha256 = "…" }
+/// "vec" = { platforms = [{ url = "https://example.com/vec0.so", sha256 = "…", os = "linux", cpu = "x86_64" }] }
+/// ```
+pub struct UrlPackage {
+    name: String,
+    platforms: Vec<SpmTomlUrlPlatform>,
+}
+
+impl UrlPackage {
+    pub fn new(name: &str, platforms: Vec<SpmTomlUrlPlatform>) -> UrlPackage {
+        UrlPackage {
+            name: name.to_owned(),
+            platforms,
+        }
+    }
+}
+
+impl PackageResolver for UrlPackage {
+    fn version_from_reference(&self) -> Result<String> {
+        self.latest_version()
+    }
+    fn toml_name(&self) -> String {
+        self.name.clone()
+    }
+    fn latest_version(&self) -> Result<String> {
+        Err(anyhow!(
+            "Extensions defined by URL do not have versions: {}",
+            self.name
+        ))
+    }
+    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
+        let mut loadable: Vec<SpmPackageJsonPlatform> = vec![];
+        let mut resolved_assets = BTreeMap::new();
+        for platform in &self.platforms {
+            let url = Url::parse(&platform.url)
+                .with_context(|| format!("Invalid URL for {}: {}", self.name, platform.url))?;
+            let asset_name = url
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| anyhow!("Cannot determine file name of {url}"))?
+                .to_owned();
+            let (os, cpu) = match (&platform.os, &platform.cpu) {
+                (Some(os), Some(cpu)) => (os.clone(), cpu.clone()),
+                _ => infer_platform(&asset_name).ok_or_else(|| {
+                    anyhow!(
+                        "Cannot determine the platform of {url} for {}, add `os` and `cpu` to its definition",
+                        self.name
+                    )
+                })?,
+            };
+            if resolved_assets
+                .insert(asset_name.clone(), url.to_string())
+                .is_some()
+            {
+                return Err(anyhow!(
+                    "URLs for {} must have distinct file names, {asset_name} is used twice",
+                    self.name
+                ));
+            }
+            loadable.push(SpmPackageJsonPlatform {
+                os,
+                cpu,
+                asset_name,
+                asset_sha256: platform.sha256.to_lowercase(),
+                asset_md5: "".to_owned(),
+            });
+        }
+
+        Ok(SpmLockExtension::Https(HttpsExtension {
+            version: "".to_owned(),
+            artifacts: definition.artifacts(),
+            resolved_spm_json: None,
+            resolved_assets,
+            integrity: "".to_owned(),
+            spm_json: SpmPackageJson {
+                version: 0,
+                description: "".to_owned(),
+                loadable,
+                static_: None,
+            },
+            inferred: false,
+            loadables_only: false,
+            registry: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{lock_and_install, tar_gz, TestServer};
+
+    #[test]
+    fn test_parse() {
+        let p = HttpsPackage::parse(
+            &Url::parse("https://artifacts.example.com/sqlite-foo/v1.2.0/spm.json").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(p.toml_name(), "https://artifacts.example.com/sqlite-foo");
+        assert_eq!(p.version, Some("v1.2.0".to_owned()));
+
+        let p = HttpsPackage::parse(
+            &Url::parse("https://artifacts.example.com/sqlite-foo@v1.2.0").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(p.toml_name(), "https://artifacts.example.com/sqlite-foo");
+        assert_eq!(p.version, Some("v1.2.0".to_owned()));
+
+        let p =
+            HttpsPackage::parse(&Url::parse("https://artifacts.example.com/sqlite-foo/").unwrap())
+                .unwrap();
+        assert_eq!(p.toml_name(), "https://artifacts.example.com/sqlite-foo");
+        assert_eq!(p.version, None);
+    }
+
+    #[test]
+    fn test_lock_and_install() {
+        let asset = tar_gz(&[("foo0.so", b"not really a shared library")]);
+        let spm_json = serde_json::json!({
+          "version": 0,
+          "description": "",
+          "loadable": [{
+            "os": "linux",
+            "cpu": "x86_64",
+            "asset_name": "sqlite-foo.tar.gz",
+            "asset_sha256": hex::encode(<sha2::Sha256 as sha2::Digest>::digest(&asset)),
+            "asset_md5": ""
+          }]
+        });
+        let server = TestServer::start(vec![
+            (
+                "/sqlite-foo/v1.2.0/spm.json".to_owned(),
+                spm_json.to_string().into_bytes(),
+            ),
+            ("/sqlite-foo/v1.2.0/sqlite-foo.tar.gz".to_owned(), asset),
+        ]);
+
+        let package =
+            HttpsPackage::parse(&Url::parse(&server.url("/sqlite-foo/v1.2.0/spm.json")).unwrap())
+                .unwrap();
+        let (lock, project) = lock_and_install(&package, "v1.2.0", "https");
+        let extension = match lock {
+            SpmLockExtension::Https(extension) => extension,
+            _ => panic!("expected an HTTPS lock entry"),
+        };
+        assert_eq!(
+            extension.resolved_assets.get("sqlite-foo.tar.gz"),
+            Some(&server.url("/sqlite-foo/v1.2.0/sqlite-foo.tar.gz"))
+        );
+        assert!(project.sqlite_extensions_path().join("foo0.so").exists());
+    }
+
+    #[test]
+    fn test_url_definitions() {
+        let asset = tar_gz(&[("vec0.so", b"linux")]);
+        let server = TestServer::start(vec![(
+            "/vec0-linux-x86_64.tar.gz".to_owned(),
+            asset.clone(),
+        )]);
+        let sha256 = hex::encode(<sha2::Sha256 as sha2::Digest>::digest(&asset));
+        let spm_toml: crate::spm::SpmToml = toml::from_str(&format!(
+            r#"
+            [extensions]
+            vec = {{ url = "{}", sha256 = "{sha256}" }}
+            bad = {{ url = "{}", sha256 = "abc", os = "linux", cpu = "x86_64" }}
+
+            [extensions.multi]
+            platforms = [
+              {{ url = "https://example.com/linux/vec0.so", sha256 = "abc", os = "linux", cpu = "x86_64" }},
+              {{ url = "https://example.com/macos/vec0.dylib", sha256 = "def", os = "macos", cpu = "aarch64" }},
+            ]
+            "#,
+            server.url("/vec0-linux-x86_64.tar.gz"),
+            server.url("/vec0-linux-x86_64.tar.gz"),
+        ))
+        .unwrap();
+        let lock = |name: &str| {
+            let (url, sha256, os, cpu) = match &spm_toml.extensions[name] {
+                SpmTomlExtensionDefinition::Url {
+                    url,
+                    sha256,
+                    os,
+                    cpu,
+                    ..
+                } => (url.clone(), sha256.clone(), os.clone(), cpu.clone()),
+                SpmTomlExtensionDefinition::Urls { platforms, .. } => {
+                    let package = UrlPackage::new(name, platforms.clone());
+                    return package.generate_lock(&spm_toml.extensions[name]).unwrap();
+                }
+                _ => panic!("expected a URL definition"),
+            };
+            UrlPackage::new(
+                name,
+                vec![SpmTomlUrlPlatform {
+                    url,
+                    sha256,
+                    os,
+                    cpu,
+                }],
+            )
+            .generate_lock(&spm_toml.extensions[name])
+            .unwrap()
+        };
+
+        let platform = Some(("linux".to_owned(), "x86_64".to_owned()));
+        let project = crate::test_server::temp_project("url");
+        match lock("vec") {
+            SpmLockExtension::Https(extension) => {
+                assert_eq!(extension.resolved_spm_json, None);
+                extension
+                    .download_platform(platform.clone(), &project)
+                    .unwrap();
+            }
+            _ => panic!("expected an HTTPS lock entry"),
+        }
+        assert!(project.sqlite_extensions_path().join("vec0.so").exists());
+        match lock("bad") {
+            SpmLockExtension::Https(extension) => {
+                let err = extension.download_platform(platform, &project).unwrap_err();
+                assert!(err.to_string().contains("sha256 checksum mismatch"));
+            }
+            _ => panic!("expected an HTTPS lock entry"),
+        }
+        match lock("multi") {
+            SpmLockExtension::Https(extension) => {
+                assert_eq!(extension.spm_json.loadable.len(), 2);
+                assert_eq!(extension.spm_json.loadable[1].asset_name, "vec0.dylib");
+            }
+            _ => panic!("expected an HTTPS lock entry"),
+        }
+    }
+}
@@ -0,0 +1,523 @@
+use crate::config::{RegistryConfig, SpmConfig};
+use crate::https::HttpsExtension;
+use crate::spm::{
+    decode_spm_json, is_not_found, latest_semver_tag, read_location, PackageResolver,
+    PackageVersion, SpmLockExtension, SpmTomlExtensionDefinition,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use url::Url;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The `index.json` at the root of a package index, listing every package
+pub struct IndexRoot {
+    pub packages: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A package listed in `index.json`
+pub struct IndexEntry {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A package file in a package index, at `{index}/{name}.json`
+pub struct IndexPackageFile {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Every published version, oldest first
+    pub versions: Vec<IndexVersion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexVersion {
+    pub version: String,
+    /// URL of the version's spm.json, relative to the package file. Assets are
+    /// stored next to spm.json.
+    pub spm_json: String,
+}
+
+/// A package index that short names are resolved through, from `[registries]` or `[index]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registry {
+    pub name: String,
+    /// Location of the index as a URL ending in `/`
+    pub url: Url,
+    /// Name prefixes that this registry is scoped to, empty for unscoped registries
+    pub prefixes: Vec<String>,
+}
+
+/// Name of the registry configured with `[index]` or `SPM_INDEX`
+pub const DEFAULT_REGISTRY: &str = "default";
+
+/// An index location as a URL ending in `/`. Local paths become `file://` URLs, where
+/// relative paths (only left over from `SPM_INDEX`) are relative to the current directory.
+fn location_url(location: &str) -> Result<Url> {
+    let location = if location.ends_with('/') {
+        location.to_owned()
+    } else {
+        format!("{location}/")
+    };
+    match Url::parse(&location) {
+        Ok(url) if url.has_host() || url.scheme() == "file" => Ok(url),
+        _ => {
+            let path = std::env::current_dir()?.join(&location);
+            Url::from_directory_path(&path)
+                .map_err(|_| anyhow!("Invalid package index location: {location}"))
+        }
+    }
+}
+
+/// Every configured registry, highest priority first. The `[index]` registry, named
+/// `default`, comes last unless a registry of that name is declared.
+pub fn registries(config: &SpmConfig) -> Result<Vec<Registry>> {
+    let mut declared: Vec<(&String, &RegistryConfig)> =
+        config.registries.iter().flatten().collect();
+    declared.sort_by_key(|(name, registry)| (std::cmp::Reverse(registry.priority), *name));
+    let mut registries = declared
+        .into_iter()
+        .map(|(name, registry)| {
+            Ok(Registry {
+                name: name.clone(),
+                url: location_url(&registry.url)?,
+                prefixes: registry.prefixes.clone().unwrap_or_default(),
+            })
+        })
+        .collect::<Result<Vec<Registry>>>()?;
+    let default = config
+        .index
+        .as_ref()
+        .and_then(|index| index.url.clone())
+        .or_else(|| std::env::var("SPM_INDEX").ok());
+    if let Some(location) = default {
+        if !registries.iter().any(|r| r.name == DEFAULT_REGISTRY) {
+            registries.push(Registry {
+                name: DEFAULT_REGISTRY.to_owned(),
+                url: location_url(&location)?,
+                prefixes: vec![],
+            });
+        }
+    }
+    Ok(registries)
+}
+
+/// The registries that may resolve `name`, in order. Names that match a scoped
+/// registry's prefix are only resolved by scoped registries, so a public registry can
+/// never stand in for an internal package.
+fn candidate_registries(name: &str, registries: Vec<Registry>) -> Vec<Registry> {
+    let (scoped, unscoped): (Vec<Registry>, Vec<Registry>) = registries
+        .into_iter()
+        .partition(|registry| !registry.prefixes.is_empty());
+    let scoped: Vec<Registry> = scoped
+        .into_iter()
+        .filter(|registry| {
+            registry
+                .prefixes
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        .collect();
+    if scoped.is_empty() {
+        unscoped
+    } else {
+        scoped
+    }
+}
+
+/// Packages in a registry whose name or description contains `term`, ignoring case
+pub fn search(index: &Url, term: &str) -> Result<Vec<IndexEntry>> {
+    let url = index.join("index.json")?;
+    let contents = read_location(url.as_str())?;
+    let root: IndexRoot = serde_json::from_slice(&contents)
+        .with_context(|| format!("{url} is not a valid package index"))?;
+    let term = term.to_lowercase();
+    Ok(root
+        .packages
+        .into_iter()
+        .filter(|entry| {
+            entry.name.to_lowercase().contains(&term)
+                || entry.description.to_lowercase().contains(&term)
+        })
+        .collect())
+}
+
+/// Resolves packages by short name, ex `hello`, through static package indexes.
+/// An index is a directory with an `index.json` listing every package, and one
+/// `{name}.json` file per package, hosted on any HTTP server or the local filesystem.
+/// With several registries, the first one that has the package resolves it.
+///
+/// Examples:
+///```
+/// "hello"
+/// "hello@v0.1.0"
+/// ```
+pub struct IndexPackage {
+    name: String,
+    registries: Vec<Registry>,
+    version: Option<String>,
+    prerelease: bool,
+}
+
+impl IndexPackage {
+    pub fn parse(reference: &str, prerelease: bool, config: &SpmConfig) -> Result<IndexPackage> {
+        IndexPackage::parse_in_registry(reference, None, prerelease, config)
+    }
+
+    /// Same as [`IndexPackage::parse`], only resolving through the named registry when
+    /// one is given
+    pub fn parse_in_registry(
+        reference: &str,
+        registry: Option<&str>,
+        prerelease: bool,
+        config: &SpmConfig,
+    ) -> Result<IndexPackage> {
+        let (name, version) = match reference.split_once('@') {
+            Some((name, version)) => (name, Some(version.to_owned())),
+            None => (reference, None),
+        };
+        let all = registries(config)?;
+        let registries = match registry {
+            Some(registry) => vec![all
+                .into_iter()
+                .find(|r| r.name == registry)
+                .ok_or_else(|| anyhow!("No registry named {registry} is configured"))?],
+            None => candidate_registries(name, all),
+        };
+        if registries.is_empty() {
+            return Err(anyhow!(
+                "No package index configured for {name}, set `[index] url` or `[registries]` in the spm config, or SPM_INDEX"
+            ));
+        }
+        Ok(IndexPackage {
+            name: name.to_owned(),
+            registries,
+            version,
+            prerelease,
+        })
+    }
+
+    /// The package file from the first registry that has it, with the registry and
+    /// the package file's URL. Only a missing package file moves on to the next registry,
+    /// any other error is returned right away.
+    fn fetch_package_file(&self) -> Result<(&Registry, Url, IndexPackageFile)> {
+        let mut errors = vec![];
+        for registry in &self.registries {
+            let url = registry
+                .url
+                .join(&format!("{}.json", self.name))
+                .with_context(|| format!("Invalid package name {}", self.name))?;
+            let contents = match read_location(url.as_str()) {
+                Ok(contents) => contents,
+                Err(err) if is_not_found(&err) => {
+                    errors.push(format!("{}: {err}", registry.name));
+                    continue;
+                }
+                Err(err) => {
+                    return Err(err.context(format!(
+                        "Could not read {} from registry {}",
+                        self.name, registry.name
+                    )))
+                }
+            };
+            let package = serde_json::from_slice(&contents)
+                .with_context(|| format!("{url} is not a valid package index file"))?;
+            return Ok((registry, url, package));
+        }
+        Err(anyhow!(
+            "{} is not in any package index ({})",
+            self.name,
+            errors.join(", ")
+        ))
+    }
+}
+
+impl PackageResolver for IndexPackage {
+    fn version_from_reference(&self) -> Result<String> {
+        match &self.version {
+            Some(v) => Ok(v.to_owned()),
+            None => self.latest_version(),
+        }
+    }
+    fn toml_name(&self) -> String {
+        self.name.clone()
+    }
+    fn latest_version(&self) -> Result<String> {
+        let (_, _, package) = self.fetch_package_file()?;
+        let versions: Vec<String> = package
+            .versions
+            .into_iter()
+            .map(|version| version.version)
+            .collect();
+        latest_semver_tag(&versions, self.prerelease)
+            .or_else(|| versions.last().cloned())
+            .ok_or_else(|| anyhow!("No versions of {} in the package index", self.name))
+    }
+    fn list_versions(&self) -> Result<Vec<PackageVersion>> {
+        let (_, _, package) = self.fetch_package_file()?;
+        Ok(package
+            .versions
+            .into_iter()
+            .map(|version| PackageVersion {
+                spm_json: Some(true),
+                ..PackageVersion::new(version.version)
+            })
+            .collect())
+    }
+    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
+        let version = definition.required_version(&self.name)?;
+        let artifacts = definition.artifacts();
+        let (registry, package_file_url, package) = self.fetch_package_file()?;
+        let entry = package
+            .versions
+            .iter()
+            .find(|entry| entry.version == version)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Version {version} of {} is not in the package index",
+                    self.name
+                )
+            })?;
+
+        let spm_json_url = package_file_url.join(&entry.spm_json)?;
+        let contents = read_location(spm_json_url.as_str())?;
+        let (spm_json, integrity) = decode_spm_json(spm_json_url.as_str(), &contents)?;
+
+        let resolved_assets = spm_json
+            .loadable
+            .iter()
+            .chain(spm_json.static_.iter().flatten())
+            .map(|platform| {
+                let asset_url = spm_json_url.join(&platform.asset_name).with_context(|| {
+                    format!(
+                        "Invalid asset name in {spm_json_url}: {}",
+                        platform.asset_name
+                    )
+                })?;
+                Ok((platform.asset_name.clone(), asset_url.to_string()))
+            })
+            .collect::<Result<BTreeMap<String, String>>>()?;
+
+        Ok(SpmLockExtension::Https(HttpsExtension {
+            version,
+            artifacts,
+            resolved_spm_json: Some(spm_json_url.to_string()),
+            resolved_assets,
+            integrity,
+            spm_json,
+            inferred: false,
+            loadables_only: false,
+            registry: Some(registry.name.clone()),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{tar_gz, temp_project, TestServer};
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn test_local_index() {
+        let project = temp_project("index");
+        let directory = project.resolve_path("index");
+        let asset = tar_gz(&[("hello0.so", b"linux")]);
+        std::fs::create_dir_all(directory.join("hello/v0.2.0")).unwrap();
+        std::fs::write(
+            directory.join("index.json"),
+            serde_json::json!({"packages": [
+              {"name": "hello", "description": "Say hello from SQL"},
+              {"name": "vec", "description": "Vector search"}
+            ]})
+            .to_string(),
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("hello.json"),
+            serde_json::json!({
+              "name": "hello",
+              "description": "Say hello from SQL",
+              "versions": [
+                {"version": "v0.1.0", "spm_json": "https://example.com/hello/v0.1.0/spm.json"},
+                {"version": "v0.2.0", "spm_json": "hello/v0.2.0/spm.json"},
+                {"version": "v0.3.0-alpha.1", "spm_json": "hello/v0.3.0-alpha.1/spm.json"}
+              ]
+            })
+            .to_string(),
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("hello/v0.2.0/spm.json"),
+            serde_json::json!({
+              "version": 0,
+              "description": "",
+              "loadable": [{
+                "os": "linux",
+                "cpu": "x86_64",
+                "asset_name": "hello-linux-x86_64.tar.gz",
+                "asset_sha256": hex::encode(Sha256::digest(&asset)),
+                "asset_md5": ""
+              }]
+            })
+            .to_string(),
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("hello/v0.2.0/hello-linux-x86_64.tar.gz"),
+            asset,
+        )
+        .unwrap();
+
+        let config: SpmConfig = toml::from_str(&format!(
+            "[index]\nurl = '{}'",
+            Url::from_directory_path(&directory).unwrap()
+        ))
+        .unwrap();
+        let index = &registries(&config).unwrap()[0];
+        assert_eq!(index.name, DEFAULT_REGISTRY);
+        let index = &index.url;
+        let results = search(index, "HELLO").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "hello");
+        assert_eq!(search(index, "vector").unwrap()[0].name, "vec");
+
+        // short names go through the index
+        let package = crate::spm::determine_package_resolver("hello", false, &config).unwrap();
+        assert_eq!(package.toml_name(), "hello");
+        assert_eq!(package.latest_version().unwrap(), "v0.2.0");
+
+        let extension = match package
+            .generate_lock(&SpmTomlExtensionDefinition::Version("v0.2.0".to_owned()))
+            .unwrap()
+        {
+            SpmLockExtension::Https(extension) => extension,
+            _ => panic!("expected an HTTPS lock entry"),
+        };
+        extension
+            .download_platform(Some(("linux".to_owned(), "x86_64".to_owned())), &project)
+            .unwrap();
+        assert_eq!(
+            std::fs::read(project.resolve_path("sqlite_extensions/hello0.so")).unwrap(),
+            b"linux"
+        );
+    }
+
+    #[test]
+    fn test_registries() {
+        let project = temp_project("registries");
+        // each registry publishes every package with its own name as the description
+        let registry = |name: &str, packages: &[&str]| {
+            let directory = project.resolve_path(name);
+            std::fs::create_dir_all(&directory).unwrap();
+            for package in packages {
+                std::fs::write(
+                    directory.join(format!("{package}.json")),
+                    serde_json::json!({
+                      "name": package,
+                      "description": name,
+                      "versions": [{"version": "v0.1.0", "spm_json": "spm.json"}]
+                    })
+                    .to_string(),
+                )
+                .unwrap();
+            }
+            std::fs::write(
+                directory.join("spm.json"),
+                r#"{"version": 0, "description": "", "loadable": []}"#,
+            )
+            .unwrap();
+            directory.display().to_string()
+        };
+        let config: SpmConfig = toml::from_str(&format!(
+            r#"
+            [registries.corp]
+            url = "{}"
+            prefixes = ["corp-"]
+
+            [registries.public]
+            url = "{}"
+
+            [registries.mirror]
+            url = "{}"
+            priority = 10
+            "#,
+            registry("corp", &["corp-vec"]),
+            registry("public", &["corp-vec", "hello", "vec"]),
+            registry("mirror", &["vec"]),
+        ))
+        .unwrap();
+        let names: Vec<String> = registries(&config)
+            .unwrap()
+            .into_iter()
+            .map(|registry| registry.name)
+            .collect();
+        assert_eq!(names, vec!["mirror", "corp", "public"]);
+
+        let resolved_from = |name: &str, registry: Option<&str>| {
+            let package = IndexPackage::parse_in_registry(name, registry, false, &config).unwrap();
+            match package
+                .generate_lock(&SpmTomlExtensionDefinition::Version("v0.1.0".to_owned()))
+                .unwrap()
+            {
+                SpmLockExtension::Https(extension) => extension.registry.unwrap(),
+                _ => panic!("expected an HTTPS lock entry"),
+            }
+        };
+        // scoped names never fall back to unscoped registries
+        assert_eq!(resolved_from("corp-vec", None), "corp");
+        assert!(IndexPackage::parse("corp-hello", false, &config)
+            .unwrap()
+            .latest_version()
+            .is_err());
+        // unscoped names resolve from the first registry that has them
+        assert_eq!(resolved_from("vec", None), "mirror");
+        assert_eq!(resolved_from("hello", None), "public");
+        assert_eq!(resolved_from("vec", Some("public")), "public");
+        assert!(IndexPackage::parse_in_registry("vec", Some("nope"), false, &config).is_err());
+    }
+
+    #[test]
+    fn test_registry_errors() {
+        let project = temp_project("registry-errors");
+        let directory = project.resolve_path("index");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("hello.json"),
+            r#"{"name": "hello", "versions": [{"version": "v0.1.0", "spm_json": "spm.json"}]}"#,
+        )
+        .unwrap();
+        let server = TestServer::start(vec![]);
+        let config = |registries: &str| -> SpmConfig {
+            toml::from_str(&format!(
+                "[registries.local]\nurl = '{}'\n\n{registries}",
+                directory.display()
+            ))
+            .unwrap()
+        };
+        let latest = |config: &SpmConfig| {
+            IndexPackage::parse("hello", false, config)
+                .unwrap()
+                .latest_version()
+        };
+
+        // a 404 or a missing directory moves on to the next registry
+        let missing = config(&format!(
+            "[registries.empty]\nurl = '{}'\npriority = 2\n\n[registries.gone]\nurl = '{}'\npriority = 1",
+            server.url("/index/"),
+            project.resolve_path("gone").display()
+        ));
+        assert_eq!(latest(&missing).unwrap(), "v0.1.0");
+
+        // a registry that can't be reached is an error, not a fallback
+        let down = config("[registries.down]\nurl = 'http://127.0.0.1:1/'\npriority = 1");
+        let err = latest(&down).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Could not read hello from registry down"));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::spm::{
+    find_loadable_platform, unpack_asset, verify_sha256, PackageResolver, Platform, Project,
+    SpmLockExtension, SpmPackageJson, SpmTomlExtensionDefinition,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// A SQLite extension on the local filesystem, either a directory with a spm.json
+/// or a single archive.
+pub struct LocalExtension {
+    /// Path to the directory or archive, as written in spm.toml
+    pub path: String,
+    /// List of artifacts to install from the extension
+    pub artifacts: Option<Vec<String>>,
+    /// The sha256 checksum of the archive, hex-encoded, when `path` is an archive
+    #[serde(rename = "asset_sha256", skip_serializing_if = "Option::is_none")]
+    pub asset_sha256: Option<String>,
+    /// The contents of spm.json, when `path` is a directory
+    #[serde(rename = "spm_json", skip_serializing_if = "Option::is_none")]
+    pub spm_json: Option<SpmPackageJson>,
+}
+
+impl LocalExtension {
+    /// Copy the platform's asset from the directory next to spm.json, or a bare archive
+    /// on every platform, re-reading it from disk and checking it against spm.lock
+    pub(crate) fn install_platform(&self, platform: Platform, project: &Project) -> Result<()> {
+        let path = project.resolve_path(&self.path);
+        match (&self.spm_json, &self.asset_sha256) {
+            (Some(spm_json), _) => {
+                let platform = find_loadable_platform(spm_json, platform)?;
+                let asset_path = path.join(&platform.asset_name);
+                let contents = read_file(&asset_path)?;
+                verify_sha256(&platform.asset_name, &contents, &platform.asset_sha256)?;
+                unpack_asset(&platform.asset_name, contents, &self.artifacts, false, project)
+            }
+            (None, Some(asset_sha256)) => {
+                let contents = read_file(&path)?;
+                verify_sha256(&self.path, &contents, asset_sha256)?;
+                unpack_asset(&self.path, contents, &self.artifacts, false, project)
+            }
+            (None, None) => Err(anyhow!(
+                "spm.lock entry for {} has neither a spm.json nor a checksum, try re-running `spm install`",
+                self.path
+            )),
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    println!("reading {} ...", path.display());
+    std::fs::read(path).with_context(|| format!("Could not read {}", path.display()))
+}
+
+/// Resolves extensions from a local directory containing spm.json, or a local archive.
+pub struct LocalPackage {
+    name: String,
+    /// path as written in spm.toml
+    path: String,
+    /// path resolved against the project directory
+    resolved_path: PathBuf,
+}
+
+impl LocalPackage {
+    pub fn new(name: &str, path: &str, resolved_path: PathBuf) -> LocalPackage {
+        LocalPackage {
+            name: name.to_owned(),
+            path: path.to_owned(),
+            resolved_path,
+        }
+    }
+}
+
+impl PackageResolver for LocalPackage {
+    fn version_from_reference(&self) -> Result<String> {
+        self.latest_version()
+    }
+    fn toml_name(&self) -> String {
+        self.name.clone()
+    }
+    fn latest_version(&self) -> Result<String> {
+        Err(anyhow!(
+            "Local extensions do not have versions: {}",
+            self.path
+        ))
+    }
+    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
+        let artifacts = definition.artifacts();
+        let path = &self.resolved_path;
+        if path.is_dir() {
+            let spm_json_path = path.join("spm.json");
+            let contents = std::fs::read(&spm_json_path)
+                .with_context(|| format!("Could not read {}", spm_json_path.display()))?;
+            let spm_json: SpmPackageJson = serde_json::from_slice(&contents)
+                .with_context(|| format!("{} is not a valid spm.json", spm_json_path.display()))?;
+            return Ok(SpmLockExtension::Local(LocalExtension {
+                path: self.path.clone(),
+                artifacts,
+                asset_sha256: None,
+                spm_json: Some(spm_json),
+            }));
+        }
+        if !(self.path.ends_with(".tar.gz") || self.path.ends_with(".zip")) {
+            return Err(anyhow!(
+                "{} must be a directory with a spm.json, or a .tar.gz or .zip archive",
+                path.display()
+            ));
+        }
+        let contents =
+            std::fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+        Ok(SpmLockExtension::Local(LocalExtension {
+            path: self.path.clone(),
+            artifacts,
+            asset_sha256: Some(hex::encode(Sha256::digest(contents))),
+            spm_json: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{tar_gz, temp_project};
+
+    #[test]
+    fn test_local_archive() {
+        let project = temp_project("local");
+        let archive = project.resolve_path("my-ext.tar.gz");
+        std::fs::write(&archive, tar_gz(&[("a0.so", b"a"), ("b0.so", b"b")])).unwrap();
+
+        let package = LocalPackage::new("my-ext", "my-ext.tar.gz", archive.clone());
+        let definition = SpmTomlExtensionDefinition::Local {
+            path: "my-ext.tar.gz".to_owned(),
+            artifacts: Some(vec!["b0".to_owned()]),
+        };
+        let extension = match package.generate_lock(&definition).unwrap() {
+            SpmLockExtension::Local(extension) => extension,
+            _ => panic!("expected a local lock entry"),
+        };
+        extension.install_platform(None, &project).unwrap();
+        assert!(!project.sqlite_extensions_path().join("a0.so").exists());
+        assert!(project.sqlite_extensions_path().join("b0.so").exists());
+
+        std::fs::write(&archive, tar_gz(&[("b0.so", b"changed")])).unwrap();
+        assert!(extension.install_platform(None, &project).is_err());
+    }
+}
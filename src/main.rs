@@ -1,5 +1,18 @@
+mod config;
+mod gitea;
+mod gitlab;
 mod http;
+mod https;
+mod index;
+mod local;
+mod npm;
+mod oci;
+mod pypi;
+mod s3;
 mod spm;
+mod sqlpkg;
+#[cfg(test)]
+mod test_server;
 
 use crate::spm::Project;
 
@@ -18,6 +31,27 @@ fn command() -> Command {
                 .help("Run spm commands in a different directory")
                 .global(true),
         )
+        .arg(
+            Arg::new("locked")
+                .long("locked")
+                .help("Fail instead of modifying spm.lock")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("offline")
+                .long("offline")
+                .help("Fail instead of making any network requests")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("frozen")
+                .long("frozen")
+                .help("Equivalent to both --locked and --offline")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
         .subcommand(
             Command::new("init")
                 // https://docs.npmjs.com/cli/v8/commands/npm-init#synopsis
@@ -37,6 +71,22 @@ fn command() -> Command {
                         .required(false),
                 ),
         )
+        .subcommand(
+            Command::new("search")
+                .about("Search the package index for SQLite extensions")
+                .arg(Arg::new("term").required(true)),
+        )
+        .subcommand(
+            Command::new("versions")
+                .about("List every published version of a SQLite extension")
+                .arg(Arg::new("package").required(true))
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the versions as JSON")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
         .subcommand(
             Command::new("install")
                 .aliases(
@@ -48,6 +98,35 @@ fn command() -> Command {
                 )
                 .about("Install all SQLite extensions in spm.toml"),
         )
+        .subcommand(
+            Command::new("update")
+                .aliases(["up", "upgrade"])
+                .about("Update locked SQLite extensions to the newest versions spm.toml allows")
+                .arg(Arg::new("packages").num_args(0..))
+                .arg(
+                    Arg::new("latest")
+                        .long("latest")
+                        .help("Also bump the constraints in spm.toml to the latest versions")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("outdated")
+                .about("List SQLite extensions with newer versions, exiting non-zero if any")
+                .arg(
+                    Arg::new("pre-release")
+                        .long("pre-release")
+                        .alias("pre")
+                        .help("Include pre-release versions")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("changelog")
+                        .long("changelog")
+                        .help("Show the release notes of each latest version")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
         .subcommand(
             Command::new("ci")
                 .aliases(
@@ -86,14 +165,49 @@ fn execute_matches(matches: ArgMatches) -> Result<()> {
             let artifacts: Option<Vec<String>> = matches
                 .get_many::<String>("artifacts")
                 .map(|v| v.into_iter().map(|v| v.to_string()).collect());
+            #[allow(clippy::unnecessary_map_or)]
             let prerelease = matches.get_one::<bool>("pre-release").map_or(false, |b| *b);
             let project = Project::resolve_from_args(matches)?;
             project.command_add(url, artifacts, prerelease)
         }
+        Some(("search", matches)) => {
+            let term = matches
+                .get_one::<String>("term")
+                .context("term is a required argument")?;
+            let project = Project::resolve_from_args(matches)?;
+            project.command_search(term)
+        }
+        Some(("versions", matches)) => {
+            let package = matches
+                .get_one::<String>("package")
+                .context("package is a required argument")?;
+            let json = matches.get_flag("json");
+            let project = Project::resolve_from_args(matches)?;
+            project.command_versions(package, json)
+        }
         Some(("install", matches)) => {
             let project = Project::resolve_from_args(matches)?;
             project.command_install()
         }
+        Some(("update", matches)) => {
+            let packages: Vec<String> = matches
+                .get_many::<String>("packages")
+                .map(|v| v.into_iter().map(|v| v.to_string()).collect())
+                .unwrap_or_default();
+            let latest = matches.get_flag("latest");
+            let project = Project::resolve_from_args(matches)?;
+            project.command_update(&packages, latest)
+        }
+        Some(("outdated", matches)) => {
+            let prerelease = matches.get_flag("pre-release");
+            let changelog = matches.get_flag("changelog");
+            let project = Project::resolve_from_args(matches)?;
+            // a non-zero status when anything is outdated, for scheduled CI checks
+            if project.command_outdated(prerelease, changelog)? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
         Some(("ci", matches)) => {
             let project = Project::resolve_from_args(matches)?;
             project.command_clean_install()
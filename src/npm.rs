@@ -0,0 +1,386 @@
+use crate::config::SpmConfig;
+use crate::https::HttpsExtension;
+use crate::spm::{
+    read_response, verify_integrity, PackageResolver, PackageVersion, SpmLockExtension,
+    SpmPackageJson, SpmPackageJsonPlatform, SpmTomlExtensionDefinition,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+/// Default npm registry
+const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org";
+
+#[derive(Debug, Deserialize)]
+/// The full metadata of a package, from `{registry}/{name}`
+struct NpmPackument {
+    #[serde(rename = "dist-tags", default)]
+    dist_tags: HashMap<String, String>,
+    #[serde(default)]
+    versions: HashMap<String, serde_json::Value>,
+    /// Publish times keyed by version, along with `created` and `modified`
+    #[serde(default)]
+    time: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+/// The metadata of a single version, from `{registry}/{name}/{version}`
+struct NpmManifest {
+    name: String,
+    version: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    os: Vec<String>,
+    #[serde(default)]
+    cpu: Vec<String>,
+    #[serde(rename = "optionalDependencies", default)]
+    optional_dependencies: BTreeMap<String, String>,
+    dist: NpmDist,
+}
+
+#[derive(Debug, Deserialize)]
+struct NpmDist {
+    tarball: String,
+    /// Subresource Integrity hash of the tarball, ex `sha512-...`
+    integrity: Option<String>,
+}
+
+/// The spm (os, cpu) platform of an npm package's `os` and `cpu` fields, if it
+/// targets exactly one supported platform
+fn npm_platform(manifest: &NpmManifest) -> Option<(&'static str, &'static str)> {
+    let allowed = |values: &[String]| -> Vec<String> {
+        values
+            .iter()
+            .filter(|value| !value.starts_with('!'))
+            .cloned()
+            .collect()
+    };
+    let (os, cpu) = match (&allowed(&manifest.os)[..], &allowed(&manifest.cpu)[..]) {
+        ([os], [cpu]) => (os.clone(), cpu.clone()),
+        _ => return None,
+    };
+    let os = match os.as_str() {
+        "linux" => "linux",
+        "darwin" => "macos",
+        "win32" => "windows",
+        _ => return None,
+    };
+    let cpu = match cpu.as_str() {
+        "x64" => "x86_64",
+        "arm64" => "aarch64",
+        _ => return None,
+    };
+    Some((os, cpu))
+}
+
+/// Resolves extensions published as platform-specific npm packages, either a single
+/// platform package or a package that lists them in its `optionalDependencies`.
+///
+/// Examples:
+///```
+/// "npm:sqlite-vec"
+/// "npm:sqlite-vec@0.1.0"
+/// "npm:sqlite-vec-linux-x64@0.1.0"
+/// "npm:@scope/sqlite-foo@1.0.0"
+/// ```
+pub struct NpmPackage {
+    /// Name of the npm package, including any scope
+    name: String,
+    /// Base URL of the npm registry, ex `https://registry.npmjs.org`
+    registry: String,
+    version: Option<String>,
+    prerelease: bool,
+}
+
+impl NpmPackage {
+    pub fn parse(reference: &str, prerelease: bool, config: &SpmConfig) -> Result<NpmPackage> {
+        // scoped packages also start with an @, ex `@scope/name@1.0.0`
+        let (name, version) = match reference.rsplit_once('@') {
+            Some((name, version)) if !name.is_empty() => (name, Some(version.to_owned())),
+            _ => (reference, None),
+        };
+        if name.is_empty() {
+            return Err(anyhow!("npm package name required: {reference}"));
+        }
+        let registry = config
+            .npm
+            .as_ref()
+            .and_then(|npm| npm.registry.clone())
+            .or_else(|| std::env::var("SPM_NPM_REGISTRY").ok())
+            .unwrap_or_else(|| DEFAULT_REGISTRY.to_owned());
+        Ok(NpmPackage {
+            name: name.to_owned(),
+            registry: registry.trim_end_matches('/').to_owned(),
+            version,
+            prerelease,
+        })
+    }
+
+    /// URL of a package's metadata, with the `/` in scoped names escaped
+    fn package_url(&self, name: &str) -> String {
+        format!("{}/{}", self.registry, name.replace('/', "%2f"))
+    }
+
+    fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        crate::http::http_get(url)?
+            .call()
+            .with_context(|| format!("call to {url} failed"))?
+            .into_json()
+            .with_context(|| format!("request did not return proper JSON: {url}"))
+    }
+
+    fn fetch_manifest(&self, name: &str, version: &str) -> Result<NpmManifest> {
+        let version = version.trim_start_matches('=');
+        self.fetch_json(&format!("{}/{version}", self.package_url(name)))
+    }
+
+    /// Download the tarball of a platform package and check its integrity, returning
+    /// the asset name and sha256 of the tarball.
+    fn verify_tarball(&self, manifest: &NpmManifest) -> Result<(String, String)> {
+        let tarball = &manifest.dist.tarball;
+        let asset_name = tarball
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| anyhow!("Cannot determine file name of {tarball}"))?
+            .to_owned();
+        let integrity = manifest.dist.integrity.as_deref().ok_or_else(|| {
+            anyhow!(
+                "{}@{} has no dist.integrity to verify",
+                manifest.name,
+                manifest.version
+            )
+        })?;
+        let contents = read_response(crate::http::http_get(tarball)?)?;
+        verify_integrity(&asset_name, &contents, integrity)?;
+        Ok((asset_name, hex::encode(Sha256::digest(contents))))
+    }
+}
+
+impl PackageResolver for NpmPackage {
+    fn version_from_reference(&self) -> Result<String> {
+        match &self.version {
+            Some(v) => Ok(v.to_owned()),
+            None => self.latest_version(),
+        }
+    }
+    fn toml_name(&self) -> String {
+        format!("npm:{}", self.name)
+    }
+    fn latest_version(&self) -> Result<String> {
+        let packument: NpmPackument = self.fetch_json(&self.package_url(&self.name))?;
+        if self.prerelease {
+            // the most recently published version, which may be a pre-release
+            if let Some((_, version)) = packument
+                .time
+                .iter()
+                .filter(|(version, _)| packument.versions.contains_key(*version))
+                .map(|(version, time)| (time, version))
+                .max()
+            {
+                return Ok(version.clone());
+            }
+        }
+        packument
+            .dist_tags
+            .get("latest")
+            .cloned()
+            .ok_or_else(|| anyhow!("No latest version found for {}", self.toml_name()))
+    }
+    fn list_versions(&self) -> Result<Vec<PackageVersion>> {
+        let packument: NpmPackument = self.fetch_json(&self.package_url(&self.name))?;
+        Ok(packument
+            .versions
+            .into_keys()
+            .map(PackageVersion::new)
+            .collect())
+    }
+    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
+        let version = definition.required_version(&self.toml_name())?;
+        let artifacts = definition.artifacts();
+        let resolved_spm_json = format!("{}/{version}", self.package_url(&self.name));
+        let manifest: NpmManifest = self.fetch_json(&resolved_spm_json)?;
+
+        let mut manifests = vec![];
+        for (name, version) in &manifest.optional_dependencies {
+            manifests.push(self.fetch_manifest(name, version)?);
+        }
+
+        let mut loadable: Vec<SpmPackageJsonPlatform> = vec![];
+        let mut resolved_assets = BTreeMap::new();
+        for platform_manifest in std::iter::once(&manifest).chain(manifests.iter()) {
+            let (os, cpu) = match npm_platform(platform_manifest) {
+                Some(platform) => platform,
+                None => continue,
+            };
+            if loadable.iter().any(|p| p.os == os && p.cpu == cpu) {
+                continue;
+            }
+            let (asset_name, asset_sha256) = self.verify_tarball(platform_manifest)?;
+            resolved_assets.insert(asset_name.clone(), platform_manifest.dist.tarball.clone());
+            loadable.push(SpmPackageJsonPlatform {
+                os: os.to_owned(),
+                cpu: cpu.to_owned(),
+                asset_name,
+                asset_sha256,
+                asset_md5: "".to_owned(),
+            });
+        }
+        if loadable.is_empty() {
+            return Err(anyhow!(
+                "No platform packages found for {} {version}",
+                self.toml_name()
+            ));
+        }
+
+        Ok(SpmLockExtension::Https(HttpsExtension {
+            version,
+            artifacts,
+            resolved_spm_json: Some(resolved_spm_json),
+            resolved_assets,
+            integrity: "".to_owned(),
+            spm_json: SpmPackageJson {
+                version: 0,
+                description: manifest.description,
+                loadable,
+                static_: None,
+            },
+            inferred: true,
+            loadables_only: true,
+            registry: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{lock_and_install, tar_gz, TestServer};
+    use base64::Engine;
+    use sha2::Sha512;
+
+    fn sri(contents: &[u8]) -> String {
+        format!(
+            "sha512-{}",
+            base64::engine::general_purpose::STANDARD.encode(Sha512::digest(contents))
+        )
+    }
+
+    #[test]
+    fn test_verify_integrity() {
+        assert!(verify_integrity("a.tgz", b"abc", &sri(b"abc")).is_ok());
+        assert!(verify_integrity("a.tgz", b"abd", &sri(b"abc")).is_err());
+        assert!(verify_integrity("a.tgz", b"abc", "md5-abc").is_err());
+    }
+
+    #[test]
+    fn test_local_registry() {
+        let tarball = tar_gz(&[
+            ("package/package.json", b"{}"),
+            ("package/README.md", b"# sqlite-vec"),
+            ("package/vec0.so", b"linux"),
+        ]);
+        let server = TestServer::start(vec![
+            (
+                "/sqlite-vec".to_owned(),
+                serde_json::json!({
+                  "dist-tags": {"latest": "0.1.0"},
+                  "versions": {"0.1.0": {}, "0.1.1-alpha.1": {}},
+                  "time": {
+                    "modified": "2024-10-01T00:00:00Z",
+                    "0.1.0": "2024-08-01T00:00:00Z",
+                    "0.1.1-alpha.1": "2024-09-01T00:00:00Z"
+                  }
+                })
+                .to_string()
+                .into_bytes(),
+            ),
+            (
+                "/sqlite-vec/0.1.0".to_owned(),
+                serde_json::json!({
+                  "name": "sqlite-vec",
+                  "version": "0.1.0",
+                  "description": "vector search",
+                  "optionalDependencies": {
+                    "sqlite-vec-linux-x64": "0.1.0",
+                    "sqlite-vec-wasm": "0.1.0"
+                  },
+                  "dist": {"tarball": "$SERVER/sqlite-vec/-/sqlite-vec-0.1.0.tgz"}
+                })
+                .to_string()
+                .into_bytes(),
+            ),
+            (
+                "/sqlite-vec-linux-x64/0.1.0".to_owned(),
+                serde_json::json!({
+                  "name": "sqlite-vec-linux-x64",
+                  "version": "0.1.0",
+                  "os": ["linux"],
+                  "cpu": ["x64"],
+                  "dist": {
+                    "tarball": "$SERVER/sqlite-vec-linux-x64/-/sqlite-vec-linux-x64-0.1.0.tgz",
+                    "integrity": sri(&tarball)
+                  }
+                })
+                .to_string()
+                .into_bytes(),
+            ),
+            (
+                "/sqlite-vec-wasm/0.1.0".to_owned(),
+                serde_json::json!({
+                  "name": "sqlite-vec-wasm",
+                  "version": "0.1.0",
+                  "dist": {"tarball": "$SERVER/sqlite-vec-wasm/-/sqlite-vec-wasm-0.1.0.tgz"}
+                })
+                .to_string()
+                .into_bytes(),
+            ),
+            (
+                "/sqlite-vec-linux-x64/-/sqlite-vec-linux-x64-0.1.0.tgz".to_owned(),
+                tarball.clone(),
+            ),
+        ]);
+
+        let config: SpmConfig =
+            toml::from_str(&format!("[npm]\nregistry = '{}'", server.url(""))).unwrap();
+        let package = NpmPackage::parse("sqlite-vec", false, &config).unwrap();
+        assert_eq!(package.toml_name(), "npm:sqlite-vec");
+        assert_eq!(package.latest_version().unwrap(), "0.1.0");
+        let package = NpmPackage::parse("sqlite-vec", true, &config).unwrap();
+        assert_eq!(package.latest_version().unwrap(), "0.1.1-alpha.1");
+
+        let (lock, project) = lock_and_install(&package, "0.1.0", "npm");
+        let extension = match lock {
+            SpmLockExtension::Https(extension) => extension,
+            _ => panic!("expected an HTTPS lock entry"),
+        };
+        assert_eq!(extension.spm_json.description, "vector search");
+        let loadable = &extension.spm_json.loadable;
+        assert_eq!(loadable.len(), 1);
+        assert_eq!(
+            (loadable[0].os.as_str(), loadable[0].cpu.as_str()),
+            ("linux", "x86_64")
+        );
+        assert_eq!(
+            loadable[0].asset_sha256,
+            hex::encode(Sha256::digest(&tarball))
+        );
+        let installed = project.resolve_path("sqlite_extensions");
+        assert_eq!(std::fs::read(installed.join("vec0.so")).unwrap(), b"linux");
+        assert!(!installed.join("package").exists());
+        assert!(!installed.join("README.md").exists());
+    }
+
+    #[test]
+    fn test_parse_scoped() {
+        let config = SpmConfig::default();
+        let package = NpmPackage::parse("@scope/sqlite-foo@1.0.0", false, &config).unwrap();
+        assert_eq!(package.toml_name(), "npm:@scope/sqlite-foo");
+        assert_eq!(package.version, Some("1.0.0".to_owned()));
+        let package = NpmPackage::parse("@scope/sqlite-foo", false, &config).unwrap();
+        assert_eq!(package.version, None);
+    }
+}
@@ -0,0 +1,444 @@
+use crate::config::SpmConfig;
+use crate::spm::{
+    find_loadable_platform, infer_platform, latest_semver_tag, unpack_asset, verify_sha256,
+    PackageResolver, PackageVersion, Platform, Project, SpmLockExtension, SpmPackageJson,
+    SpmPackageJsonPlatform, SpmTomlExtensionDefinition,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use url::Url;
+
+/// Manifest media types that spm understands
+const MANIFEST_ACCEPT: &str =
+    "application/vnd.oci.image.manifest.v1+json, application/vnd.docker.distribution.manifest.v2+json";
+
+/// Layer annotation with the file name of the layer, set by tools like `oras push`
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+/// Layer annotations with the spm os and cpu of a layer
+const OS_ANNOTATION: &str = "spm.os";
+const CPU_ANNOTATION: &str = "spm.cpu";
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// A SQLite extension published as an OCI artifact, with one layer per platform.
+pub struct OciExtension {
+    /// The tag (or digest) of the artifact
+    pub version: String,
+    /// List of artifacts to install from the extension
+    pub artifacts: Option<Vec<String>>,
+    /// Full URL to the manifest for this version
+    #[serde(rename = "resolved_manifest")]
+    pub resolved_manifest: String,
+    /// Digest of the manifest, ex `sha256:...`
+    #[serde(rename = "manifest_digest")]
+    pub manifest_digest: String,
+    /// Full URLs of every layer blob, keyed by asset name
+    #[serde(rename = "resolved_assets")]
+    pub resolved_assets: BTreeMap<String, String>,
+    /// A spm.json inferred from the manifest's layers, with layer digests as checksums
+    #[serde(rename = "spm_json")]
+    pub spm_json: SpmPackageJson,
+}
+
+impl OciExtension {
+    /// Pull the platform's layer blob from the registry, authenticating with a token
+    /// when challenged, and check it against its layer digest
+    pub(crate) fn download_platform(&self, platform: Platform, project: &Project) -> Result<()> {
+        let platform = find_loadable_platform(&self.spm_json, platform)?;
+        let url = self
+            .resolved_assets
+            .get(&platform.asset_name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "No resolved URL for {} in spm.lock, try re-running `spm install`",
+                    platform.asset_name
+                )
+            })?;
+        println!("downloading {url} ...");
+        let mut contents = Vec::new();
+        registry_get(url, "application/octet-stream")?
+            .into_reader()
+            .read_to_end(&mut contents)
+            .with_context(|| format!("Error reading response from {url}"))?;
+        verify_sha256(&platform.asset_name, &contents, &platform.asset_sha256)?;
+        unpack_asset(
+            &platform.asset_name,
+            contents,
+            &self.artifacts,
+            false,
+            project,
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OciManifest {
+    #[serde(default)]
+    media_type: Option<String>,
+    #[serde(default)]
+    layers: Vec<OciDescriptor>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OciDescriptor {
+    digest: String,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OciTags {
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OciToken {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// Parse the parameters of a `WWW-Authenticate: Bearer realm="...",service="..."` challenge
+fn parse_challenge(challenge: &str) -> Option<HashMap<String, String>> {
+    let mut rest = challenge.trim().strip_prefix("Bearer ")?.trim();
+    let mut params = HashMap::new();
+    while let Some((key, value)) = rest.split_once('=') {
+        let (value, remaining) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        params.insert(key.trim().to_owned(), value.to_owned());
+        rest = remaining.trim_start_matches(',').trim();
+    }
+    Some(params)
+}
+
+/// Request a registry URL. Registries that answer with a Bearer challenge, even for
+/// public artifacts like ghcr.io does, are retried with a token from their auth service.
+fn registry_get(url: &str, accept: &str) -> Result<ureq::Response> {
+    let challenge = match crate::http::http_get(url)?.set("Accept", accept).call() {
+        Err(ureq::Error::Status(401, response)) => response
+            .header("WWW-Authenticate")
+            .and_then(parse_challenge)
+            .ok_or_else(|| anyhow!("{url} requires authentication"))?,
+        result => return result.with_context(|| format!("call to {url} failed")),
+    };
+    let realm = challenge
+        .get("realm")
+        .ok_or_else(|| anyhow!("authentication challenge from {url} has no realm"))?;
+    let mut token_url =
+        Url::parse(realm).with_context(|| format!("invalid authentication realm {realm}"))?;
+    for key in ["service", "scope"] {
+        if let Some(value) = challenge.get(key) {
+            token_url.query_pairs_mut().append_pair(key, value);
+        }
+    }
+    let token: OciToken = crate::http::http_get(token_url.as_str())?
+        .call()
+        .with_context(|| format!("call to {token_url} failed"))?
+        .into_json()
+        .with_context(|| format!("request did not return proper JSON: {token_url}"))?;
+    let token = token
+        .token
+        .or(token.access_token)
+        .ok_or_else(|| anyhow!("{token_url} did not return a token"))?;
+    crate::http::http_get(url)?
+        .set("Accept", accept)
+        .set("Authorization", &format!("Bearer {token}"))
+        .call()
+        .with_context(|| format!("call to {url} failed"))
+}
+
+/// Resolves extensions published as OCI artifacts in a container registry, with one
+/// layer per platform.
+///
+/// Examples:
+///```
+/// "oci://ghcr.io/asg017/sqlite-foo"
+/// "oci://ghcr.io/asg017/sqlite-foo:v1.0.0"
+/// "oci://registry.mycorp.com:5000/extensions/sqlite-foo@sha256:abc..."
+/// ```
+pub struct OciPackage {
+    /// Registry host, with port if any, ex `ghcr.io`
+    registry: String,
+    /// Base URL of the registry, ex `https://ghcr.io`
+    base_url: String,
+    /// Repository within the registry, ex `asg017/sqlite-foo`
+    repository: String,
+    version: Option<String>,
+    prerelease: bool,
+}
+
+impl OciPackage {
+    /// `reference` is everything after `oci://`. Registries on localhost are reached over
+    /// plain HTTP, and a `url` in the host's config overrides the base URL.
+    pub fn parse(reference: &str, prerelease: bool, config: &SpmConfig) -> Result<OciPackage> {
+        let (registry, path) = reference
+            .split_once('/')
+            .ok_or_else(|| anyhow!("OCI references look like registry/repository: {reference}"))?;
+        let (repository, version) = match path.rsplit_once('@') {
+            Some((repository, digest)) => (repository, Some(digest.to_owned())),
+            None => match path.rsplit_once(':') {
+                Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag.to_owned())),
+                _ => (path, None),
+            },
+        };
+        if registry.is_empty() || repository.is_empty() {
+            return Err(anyhow!(
+                "OCI references look like registry/repository: {reference}"
+            ));
+        }
+        let hostname = registry.split(':').next().unwrap_or(registry);
+        let scheme = if matches!(hostname, "localhost" | "127.0.0.1") {
+            "http"
+        } else {
+            "https"
+        };
+        let default_url = format!("{scheme}://{registry}");
+        let base_url = Url::parse(&default_url)
+            .ok()
+            .and_then(|url| config.host(&url).and_then(|host| host.url.clone()))
+            .unwrap_or(default_url);
+        Ok(OciPackage {
+            registry: registry.to_owned(),
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            repository: repository.to_owned(),
+            version,
+            prerelease,
+        })
+    }
+
+    fn repository_url(&self) -> String {
+        format!("{}/v2/{}", self.base_url, self.repository)
+    }
+
+    /// Every tag of the repository
+    fn tags(&self) -> Result<Vec<String>> {
+        let url = format!("{}/tags/list", self.repository_url());
+        let tags: OciTags = registry_get(&url, "application/json")?
+            .into_json()
+            .with_context(|| format!("request did not return proper JSON: {url}"))?;
+        Ok(tags.tags.unwrap_or_default())
+    }
+}
+
+impl PackageResolver for OciPackage {
+    fn version_from_reference(&self) -> Result<String> {
+        match &self.version {
+            Some(v) => Ok(v.to_owned()),
+            None => self.latest_version(),
+        }
+    }
+    fn toml_name(&self) -> String {
+        format!("oci://{}/{}", self.registry, self.repository)
+    }
+    fn latest_version(&self) -> Result<String> {
+        let tags = self.tags()?;
+        // the highest semver tag, or the `latest` tag when no tags are versions
+        latest_semver_tag(&tags, self.prerelease)
+            .or_else(|| tags.iter().find(|tag| *tag == "latest").cloned())
+            .ok_or_else(|| anyhow!("No version tags found for {}", self.toml_name()))
+    }
+    fn list_versions(&self) -> Result<Vec<PackageVersion>> {
+        Ok(self.tags()?.into_iter().map(PackageVersion::new).collect())
+    }
+    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
+        let version = definition.required_version(&self.toml_name())?;
+        let artifacts = definition.artifacts();
+
+        let resolved_manifest = format!("{}/manifests/{version}", self.repository_url());
+        let mut contents = Vec::new();
+        registry_get(&resolved_manifest, MANIFEST_ACCEPT)?
+            .into_reader()
+            .read_to_end(&mut contents)
+            .with_context(|| format!("Error reading response from {resolved_manifest}"))?;
+        let manifest_digest = format!("sha256:{}", hex::encode(Sha256::digest(&contents)));
+        if version.starts_with("sha256:") && version != manifest_digest {
+            return Err(anyhow!(
+                "manifest digest mismatch for {}: expected {version}, got {manifest_digest}",
+                self.toml_name()
+            ));
+        }
+        let manifest: OciManifest = serde_json::from_slice(&contents)
+            .with_context(|| format!("{resolved_manifest} is not a valid OCI manifest"))?;
+        if manifest
+            .media_type
+            .as_deref()
+            .is_some_and(|media_type| media_type.contains("index") || media_type.contains("list"))
+        {
+            return Err(anyhow!(
+                "{resolved_manifest} is an image index, spm expects a single manifest with a layer per platform"
+            ));
+        }
+
+        let mut loadable: Vec<SpmPackageJsonPlatform> = vec![];
+        let mut resolved_assets = BTreeMap::new();
+        for layer in &manifest.layers {
+            let asset_sha256 = layer.digest.strip_prefix("sha256:").ok_or_else(|| {
+                anyhow!(
+                    "Unsupported digest algorithm for layer {} of {}",
+                    layer.digest,
+                    self.toml_name()
+                )
+            })?;
+            let asset_name = match layer.annotations.get(TITLE_ANNOTATION) {
+                Some(title) => title.clone(),
+                None => continue,
+            };
+            let platform = match (
+                layer.annotations.get(OS_ANNOTATION),
+                layer.annotations.get(CPU_ANNOTATION),
+            ) {
+                (Some(os), Some(cpu)) => Some((os.clone(), cpu.clone())),
+                _ => infer_platform(&asset_name),
+            };
+            let (os, cpu) = match platform {
+                Some(platform) => platform,
+                None => continue,
+            };
+            resolved_assets.insert(
+                asset_name.clone(),
+                format!("{}/blobs/{}", self.repository_url(), layer.digest),
+            );
+            loadable.push(SpmPackageJsonPlatform {
+                os,
+                cpu,
+                asset_name,
+                asset_sha256: asset_sha256.to_owned(),
+                asset_md5: "".to_owned(),
+            });
+        }
+        if loadable.is_empty() {
+            return Err(anyhow!(
+                "No platform layers found in {resolved_manifest}, layers need a {TITLE_ANNOTATION} annotation"
+            ));
+        }
+
+        Ok(SpmLockExtension::Oci(OciExtension {
+            version,
+            artifacts,
+            resolved_manifest,
+            manifest_digest,
+            resolved_assets,
+            spm_json: SpmPackageJson {
+                version: 0,
+                description: "".to_owned(),
+                loadable,
+                static_: None,
+            },
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{lock_and_install, tar_gz, TestServer};
+
+    #[test]
+    fn test_parse() {
+        let config = SpmConfig::default();
+        let package = OciPackage::parse("ghcr.io/asg017/sqlite-foo:v1.0", false, &config).unwrap();
+        assert_eq!(package.toml_name(), "oci://ghcr.io/asg017/sqlite-foo");
+        assert_eq!(package.base_url, "https://ghcr.io");
+        assert_eq!(package.version, Some("v1.0".to_owned()));
+
+        let package =
+            OciPackage::parse("localhost:5000/sqlite-foo@sha256:abc", false, &config).unwrap();
+        assert_eq!(
+            package.repository_url(),
+            "http://localhost:5000/v2/sqlite-foo"
+        );
+        assert_eq!(package.version, Some("sha256:abc".to_owned()));
+
+        assert!(OciPackage::parse("sqlite-foo", false, &config).is_err());
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        let params = parse_challenge(
+            r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:a/b:pull,push""#,
+        )
+        .unwrap();
+        assert_eq!(params["realm"], "https://ghcr.io/token");
+        assert_eq!(params["service"], "ghcr.io");
+        assert_eq!(params["scope"], "repository:a/b:pull,push");
+        assert!(parse_challenge("Basic realm=\"x\"").is_none());
+    }
+
+    #[test]
+    fn test_local_registry() {
+        let linux = tar_gz(&[("foo0.so", b"linux")]);
+        let linux_digest = format!("sha256:{}", hex::encode(Sha256::digest(&linux)));
+        let manifest = serde_json::json!({
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.manifest.v1+json",
+          "layers": [
+            {
+              "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+              "digest": linux_digest,
+              "annotations": {"org.opencontainers.image.title": "foo-linux-x86_64.tar.gz"}
+            },
+            {
+              "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+              "digest": "sha256:abc",
+              "annotations": {
+                "org.opencontainers.image.title": "foo-mac.tar.gz",
+                "spm.os": "macos",
+                "spm.cpu": "aarch64"
+              }
+            }
+          ]
+        })
+        .to_string();
+        let manifest_digest = format!("sha256:{}", hex::encode(Sha256::digest(&manifest)));
+        let server = TestServer::start(vec![
+            (
+                "/v2/ns/sqlite-foo/tags/list".to_owned(),
+                br#"{"name": "ns/sqlite-foo", "tags": ["latest", "v0.9.0", "v1.0.0", "v1.1.0-alpha.1"]}"#.to_vec(),
+            ),
+            (
+                "/v2/ns/sqlite-foo/manifests/v1.0.0".to_owned(),
+                manifest.into_bytes(),
+            ),
+            (
+                format!("/v2/ns/sqlite-foo/blobs/{linux_digest}"),
+                linux.clone(),
+            ),
+        ]);
+        let registry = server.url("").trim_start_matches("http://").to_owned();
+
+        let package = OciPackage::parse(
+            &format!("{registry}/ns/sqlite-foo"),
+            false,
+            &SpmConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(package.latest_version().unwrap(), "v1.0.0");
+
+        let (lock, project) = lock_and_install(&package, "v1.0.0", "oci");
+        let extension = match lock {
+            SpmLockExtension::Oci(extension) => extension,
+            _ => panic!("expected an OCI lock entry"),
+        };
+        assert_eq!(extension.manifest_digest, manifest_digest);
+        let loadable = &extension.spm_json.loadable;
+        assert_eq!(
+            (loadable[0].os.as_str(), loadable[0].cpu.as_str()),
+            ("linux", "x86_64")
+        );
+        assert_eq!(
+            (loadable[1].os.as_str(), loadable[1].cpu.as_str()),
+            ("macos", "aarch64")
+        );
+        assert_eq!(
+            std::fs::read(project.resolve_path("sqlite_extensions/foo0.so")).unwrap(),
+            b"linux"
+        );
+    }
+}
@@ -0,0 +1,372 @@
+use crate::config::SpmConfig;
+use crate::https::HttpsExtension;
+use crate::spm::{
+    PackageResolver, PackageVersion, SpmLockExtension, SpmPackageJson, SpmPackageJsonPlatform,
+    SpmTomlExtensionDefinition,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Default Python package index
+const DEFAULT_INDEX_URL: &str = "https://pypi.org";
+
+#[derive(Debug, Deserialize)]
+/// Response of the `/pypi/{name}/json` endpoint
+struct PypiProject {
+    info: PypiInfo,
+    #[serde(default)]
+    releases: HashMap<String, Vec<PypiFile>>,
+}
+
+#[derive(Debug, Deserialize)]
+/// Response of the `/pypi/{name}/{version}/json` endpoint
+struct PypiRelease {
+    info: PypiInfo,
+    urls: Vec<PypiFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PypiInfo {
+    /// The latest non-prerelease version on the project endpoint, or the requested
+    /// version on the release endpoint
+    version: String,
+    #[serde(default)]
+    summary: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PypiFile {
+    filename: String,
+    url: String,
+    #[serde(default)]
+    packagetype: String,
+    #[serde(default)]
+    digests: HashMap<String, String>,
+    #[serde(default)]
+    upload_time_iso_8601: Option<String>,
+    #[serde(default)]
+    yanked: bool,
+}
+
+/// The spm (os, cpu) platforms that a wheel's platform tag supports, ex
+/// `manylinux_2_17_x86_64.manylinux2014_x86_64` -> [("linux", "x86_64")]
+fn wheel_platforms(filename: &str) -> Vec<(&'static str, &'static str)> {
+    let platform_tag = filename
+        .trim_end_matches(".whl")
+        .rsplit('-')
+        .next()
+        .unwrap_or("");
+    let mut platforms = vec![];
+    for tag in platform_tag.split('.') {
+        let os = if tag.starts_with("manylinux")
+            || tag.starts_with("musllinux")
+            || tag.starts_with("linux_")
+        {
+            "linux"
+        } else if tag.starts_with("macosx_") {
+            "macos"
+        } else if tag.starts_with("win_") {
+            "windows"
+        } else {
+            continue;
+        };
+        let cpus: &[&str] = if tag.ends_with("x86_64") || tag.ends_with("amd64") {
+            &["x86_64"]
+        } else if tag.ends_with("aarch64") || tag.ends_with("arm64") {
+            &["aarch64"]
+        } else if tag.ends_with("universal2") {
+            &["x86_64", "aarch64"]
+        } else {
+            continue;
+        };
+        for cpu in cpus {
+            if !platforms.contains(&(os, *cpu)) {
+                platforms.push((os, *cpu));
+            }
+        }
+    }
+    platforms
+}
+
+/// Whether a PEP 440 version is a pre-release or development release, ex `0.1.1a1`
+fn is_prerelease_version(version: &str) -> bool {
+    ["a", "b", "rc", "dev"]
+        .iter()
+        .any(|marker| version.contains(marker))
+}
+
+/// Resolves extensions that ship their loadable library inside the wheels of a
+/// Python package, using the JSON API of PyPI or a compatible index.
+///
+/// Examples:
+///```
+/// "pypi:sqlite-vec"
+/// "pypi:sqlite-vec@0.1.0"
+/// ```
+pub struct PypiPackage {
+    /// Name of the Python package, ex `sqlite-vec`
+    name: String,
+    /// Base URL of the package index, ex `https://pypi.org`
+    index_url: String,
+    version: Option<String>,
+    prerelease: bool,
+}
+
+impl PypiPackage {
+    pub fn parse(reference: &str, prerelease: bool, config: &SpmConfig) -> Result<PypiPackage> {
+        let (name, version) = match reference.split_once('@') {
+            Some((name, version)) => (name, Some(version.to_owned())),
+            None => (reference, None),
+        };
+        if name.is_empty() {
+            return Err(anyhow!("pypi package name required: {reference}"));
+        }
+        let index_url = config
+            .pypi
+            .as_ref()
+            .and_then(|pypi| pypi.url.clone())
+            .or_else(|| std::env::var("SPM_PYPI_URL").ok())
+            .unwrap_or_else(|| DEFAULT_INDEX_URL.to_owned());
+        Ok(PypiPackage {
+            name: name.to_owned(),
+            index_url: index_url.trim_end_matches('/').to_owned(),
+            version,
+            prerelease,
+        })
+    }
+
+    fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        crate::http::http_get(url)?
+            .call()
+            .with_context(|| format!("call to {url} failed"))?
+            .into_json()
+            .with_context(|| format!("request did not return proper JSON: {url}"))
+    }
+
+    fn release_url(&self, version: &str) -> String {
+        format!("{}/pypi/{}/{version}/json", self.index_url, self.name)
+    }
+}
+
+impl PackageResolver for PypiPackage {
+    fn version_from_reference(&self) -> Result<String> {
+        match &self.version {
+            Some(v) => Ok(v.to_owned()),
+            None => self.latest_version(),
+        }
+    }
+    fn toml_name(&self) -> String {
+        format!("pypi:{}", self.name)
+    }
+    fn latest_version(&self) -> Result<String> {
+        let url = format!("{}/pypi/{}/json", self.index_url, self.name);
+        let project: PypiProject = self.fetch_json(&url)?;
+        if !self.prerelease {
+            return Ok(project.info.version);
+        }
+        // the most recently uploaded release, which may be a pre-release
+        project
+            .releases
+            .into_iter()
+            .filter_map(|(version, files)| {
+                files
+                    .iter()
+                    .filter(|file| !file.yanked)
+                    .filter_map(|file| file.upload_time_iso_8601.clone())
+                    .max()
+                    .map(|uploaded| (uploaded, version))
+            })
+            .max()
+            .map(|(_, version)| version)
+            .ok_or_else(|| anyhow!("No releases found for {}", self.toml_name()))
+    }
+    fn list_versions(&self) -> Result<Vec<PackageVersion>> {
+        let url = format!("{}/pypi/{}/json", self.index_url, self.name);
+        let project: PypiProject = self.fetch_json(&url)?;
+        Ok(project
+            .releases
+            .into_iter()
+            .filter(|(_, files)| files.iter().any(|file| !file.yanked))
+            .map(|(version, files)| {
+                let mut platforms: Vec<String> = files
+                    .iter()
+                    .filter(|file| file.packagetype == "bdist_wheel" && !file.yanked)
+                    .flat_map(|file| wheel_platforms(&file.filename))
+                    .map(|(os, cpu)| format!("{os}-{cpu}"))
+                    .collect();
+                platforms.sort();
+                platforms.dedup();
+                PackageVersion {
+                    prerelease: is_prerelease_version(&version),
+                    version,
+                    spm_json: None,
+                    platforms,
+                }
+            })
+            .collect())
+    }
+    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
+        let version = definition.required_version(&self.toml_name())?;
+        let artifacts = definition.artifacts();
+        let resolved_spm_json = self.release_url(&version);
+        let release: PypiRelease = self.fetch_json(&resolved_spm_json)?;
+
+        let mut wheels: Vec<&PypiFile> = release
+            .urls
+            .iter()
+            .filter(|file| file.packagetype == "bdist_wheel" && !file.yanked)
+            .collect();
+        // prefer glibc wheels over musl ones for linux
+        wheels.sort_by_key(|file| file.filename.contains("musllinux"));
+
+        let mut loadable: Vec<SpmPackageJsonPlatform> = vec![];
+        let mut resolved_assets = BTreeMap::new();
+        for wheel in wheels {
+            let asset_sha256 = wheel.digests.get("sha256").ok_or_else(|| {
+                anyhow!(
+                    "{} has no sha256 digest in {resolved_spm_json}",
+                    wheel.filename
+                )
+            })?;
+            for (os, cpu) in wheel_platforms(&wheel.filename) {
+                if loadable.iter().any(|p| p.os == os && p.cpu == cpu) {
+                    continue;
+                }
+                resolved_assets.insert(wheel.filename.clone(), wheel.url.clone());
+                loadable.push(SpmPackageJsonPlatform {
+                    os: os.to_owned(),
+                    cpu: cpu.to_owned(),
+                    asset_name: wheel.filename.clone(),
+                    asset_sha256: asset_sha256.clone(),
+                    asset_md5: "".to_owned(),
+                });
+            }
+        }
+        if loadable.is_empty() {
+            return Err(anyhow!(
+                "No platform wheels found for {} {version}",
+                self.toml_name()
+            ));
+        }
+
+        Ok(SpmLockExtension::Https(HttpsExtension {
+            version,
+            artifacts,
+            resolved_spm_json: Some(resolved_spm_json),
+            resolved_assets,
+            integrity: "".to_owned(),
+            spm_json: SpmPackageJson {
+                version: 0,
+                description: release.info.summary.unwrap_or_default(),
+                loadable,
+                static_: None,
+            },
+            inferred: true,
+            loadables_only: true,
+            registry: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{lock_and_install, zip, TestServer};
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn test_wheel_platforms() {
+        assert_eq!(
+            wheel_platforms(
+                "sqlite_vec-0.1.0-py3-none-manylinux_2_17_x86_64.manylinux2014_x86_64.whl"
+            ),
+            vec![("linux", "x86_64")]
+        );
+        assert_eq!(
+            wheel_platforms("sqlite_vec-0.1.0-py3-none-macosx_10_9_universal2.whl"),
+            vec![("macos", "x86_64"), ("macos", "aarch64")]
+        );
+        assert_eq!(
+            wheel_platforms("sqlite_vec-0.1.0-py3-none-win_amd64.whl"),
+            vec![("windows", "x86_64")]
+        );
+        assert!(wheel_platforms("sqlite_vec-0.1.0-py3-none-any.whl").is_empty());
+    }
+
+    #[test]
+    fn test_local_index() {
+        let wheel = zip(&[
+            ("sqlite_vec/__init__.py", b"import sqlite3"),
+            ("sqlite_vec/vec0.so", b"linux"),
+            ("sqlite_vec-0.1.0.dist-info/METADATA", b"Name: sqlite-vec"),
+        ]);
+        let filename = "sqlite_vec-0.1.0-py3-none-manylinux_2_17_x86_64.whl";
+        let file = |filename: &str, sha256: &str| {
+            serde_json::json!({
+              "filename": filename,
+              "url": format!("$SERVER/files/{filename}"),
+              "packagetype": "bdist_wheel",
+              "digests": {"sha256": sha256},
+              "upload_time_iso_8601": "2024-08-01T00:00:00Z"
+            })
+        };
+        let server = TestServer::start(vec![
+            (
+                "/pypi/sqlite-vec/json".to_owned(),
+                serde_json::json!({
+                  "info": {"version": "0.1.0"},
+                  "releases": {
+                    "0.1.0": [file(filename, "")],
+                    "0.1.1a1": [{
+                      "filename": "sqlite_vec-0.1.1a1-py3-none-win_amd64.whl",
+                      "url": "",
+                      "upload_time_iso_8601": "2024-09-01T00:00:00Z"
+                    }]
+                  }
+                })
+                .to_string()
+                .into_bytes(),
+            ),
+            (
+                "/pypi/sqlite-vec/0.1.0/json".to_owned(),
+                serde_json::json!({
+                  "info": {"version": "0.1.0", "summary": "vector search"},
+                  "urls": [
+                    file(
+                      "sqlite_vec-0.1.0-py3-none-musllinux_1_1_x86_64.whl",
+                      "unused"
+                    ),
+                    file(filename, &hex::encode(Sha256::digest(&wheel))),
+                    file("sqlite_vec-0.1.0-py3-none-any.whl", "unused")
+                  ]
+                })
+                .to_string()
+                .into_bytes(),
+            ),
+            (format!("/files/{filename}"), wheel.clone()),
+        ]);
+
+        let config: SpmConfig =
+            toml::from_str(&format!("[pypi]\nurl = '{}'", server.url(""))).unwrap();
+        let package = PypiPackage::parse("sqlite-vec", false, &config).unwrap();
+        assert_eq!(package.toml_name(), "pypi:sqlite-vec");
+        assert_eq!(package.latest_version().unwrap(), "0.1.0");
+        let package = PypiPackage::parse("sqlite-vec", true, &config).unwrap();
+        assert_eq!(package.latest_version().unwrap(), "0.1.1a1");
+
+        let (lock, project) = lock_and_install(&package, "0.1.0", "pypi");
+        let extension = match lock {
+            SpmLockExtension::Https(extension) => extension,
+            _ => panic!("expected an HTTPS lock entry"),
+        };
+        assert_eq!(extension.spm_json.description, "vector search");
+        assert_eq!(extension.spm_json.loadable.len(), 1);
+        assert_eq!(extension.spm_json.loadable[0].asset_name, filename);
+        let installed = project.resolve_path("sqlite_extensions");
+        assert_eq!(std::fs::read(installed.join("vec0.so")).unwrap(), b"linux");
+        assert!(!installed.join("__init__.py").exists());
+        assert!(!installed.join("METADATA").exists());
+    }
+}
@@ -1,12 +1,26 @@
 use clap::ArgMatches;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
-use std::{collections::HashMap, ffi::OsString, process::Stdio, str::Split};
+use std::{ffi::OsString, process::Stdio, str::Split};
 use zip::ZipArchive;
 
+use crate::config::{host_key, HostConfig, HostKind, RegistryConfig, SpmConfig};
+use crate::gitea::GiteaReleasePackage;
+use crate::gitlab::GitlabReleasePackage;
+use crate::https::{HttpsExtension, HttpsPackage, UrlPackage};
+use crate::index::IndexPackage;
+use crate::local::{LocalExtension, LocalPackage};
+use crate::npm::NpmPackage;
+use crate::oci::{OciExtension, OciPackage};
+use crate::pypi::PypiPackage;
+use crate::s3::{S3Extension, S3Package};
+use crate::sqlpkg::SqlpkgPackage;
+
 use anyhow::{anyhow, Context, Result};
+use base64::Engine;
 use flate2::read::GzDecoder;
-use sha2::{Digest, Sha256};
+use sha2::{Digest, Sha256, Sha512};
 use std::io::{BufReader, Read};
 use tar::Archive;
 use toml_edit::{value, Array, Document, InlineTable, Item};
@@ -17,6 +31,8 @@ pub(crate) struct Project {
     spm_toml_path: PathBuf,
     spm_lock_path: PathBuf,
     sqlite_extensions_path: PathBuf,
+    /// With `--locked` or `--frozen`, spm.lock may not be modified
+    locked: bool,
 }
 
 #[cfg(target_os = "linux")]
@@ -36,14 +52,29 @@ impl Project {
             spm_toml_path,
             spm_lock_path,
             sqlite_extensions_path,
+            locked: false,
         }
     }
     pub fn resolve_from_args(matches: &ArgMatches) -> Result<Project> {
-        match matches.get_one::<String>("prefix") {
-            Some(base_directory) => Ok(Project::new(base_directory.into())),
+        let mut project = match matches.get_one::<String>("prefix") {
+            Some(base_directory) => Project::new(base_directory.into()),
             // TODO traverse up the folder tree to find nearest directory with a spm.toml
-            None => Ok(Project::new(std::env::current_dir()?)),
+            None => Project::new(std::env::current_dir()?),
+        };
+        let frozen = matches.get_flag("frozen");
+        project.locked = frozen || matches.get_flag("locked");
+        crate::http::set_offline(frozen || matches.get_flag("offline"));
+        Ok(project)
+    }
+
+    /// Fail if spm.lock may not be modified, because of `--locked` or `--frozen`
+    fn ensure_unlocked(&self, operation: &str) -> Result<()> {
+        if self.locked {
+            return Err(anyhow!(
+                "{operation} needs to update spm.lock, but --locked or --frozen was passed"
+            ));
         }
+        Ok(())
     }
     /// Implements `spm init`
     pub fn command_init(&self) -> Result<()> {
@@ -64,24 +95,32 @@ impl Project {
         artifacts: Option<Vec<String>>,
         prerelease: bool,
     ) -> Result<()> {
-        let pkg_resolver = determine_package_resolver(url, prerelease)?;
+        self.ensure_unlocked("spm add")?;
+        let config = self.load_config()?;
+        let pkg_resolver = determine_package_resolver(url, prerelease, &config)?;
         let version = pkg_resolver.version_from_reference()?;
 
         let spm_toml_contents = self.read_spm_toml_contents()?;
         let mut doc = spm_toml_contents
             .parse::<Document>()
             .context("invalid spm.toml")?;
-        doc["extensions"][pkg_resolver.toml_name().as_str()] = match artifacts {
-            Some(artifacts) => {
+        doc["extensions"][pkg_resolver.toml_name().as_str()] = match (artifacts, prerelease) {
+            (None, false) => value(version),
+            (artifacts, prerelease) => {
                 let mut t = InlineTable::new();
                 t.insert("version", version.into());
-                t.insert(
-                    "artifacts",
-                    toml_edit::Value::Array(Array::from_iter(artifacts)),
-                );
+                if let Some(artifacts) = artifacts {
+                    t.insert(
+                        "artifacts",
+                        toml_edit::Value::Array(Array::from_iter(artifacts)),
+                    );
+                }
+                // persisted, so that later installs and updates also allow pre-releases
+                if prerelease {
+                    t.insert("prerelease", true.into());
+                }
                 Item::Value(toml_edit::Value::InlineTable(t))
             }
-            None => value(version),
         };
 
         self.write_spm_toml_contents(doc.to_string())?;
@@ -100,11 +139,314 @@ impl Project {
 
     /// Implements `spm ci`
     pub fn command_clean_install(&self) -> Result<()> {
-        // TODO verify that spm.toml and spm.lock are consistent, and exit if not
+        self.check_lockfile()?;
+        // like `npm ci`, start from an empty sqlite_extensions/
+        if self.sqlite_extensions_exists() {
+            std::fs::remove_dir_all(&self.sqlite_extensions_path).with_context(|| {
+                format!("Could not remove {}", self.sqlite_extensions_path.display())
+            })?;
+        }
+        self.create_sqlite_extensions_dir()?;
+        self.write_in_sqlite_extensions(".gitignore".into(), "*")?;
+        self.install(None)?;
+        Ok(())
+    }
+
+    /// Verify that spm.lock was generated from the current spm.toml, explaining every
+    /// extension that is missing, extra or locked to a version that doesn't match
+    fn check_lockfile(&self) -> Result<()> {
+        let spm_toml = self.read_spm_toml()?;
+        if !self.spm_lock_path.exists() {
+            return Err(anyhow!("No spm.lock found, run `spm install` to create it"));
+        }
+        let spm_lock = self.read_spm_lock()?;
+        let mut problems = vec![];
+        let mut names: Vec<&String> = spm_toml.extensions.keys().collect();
+        names.sort();
+        for name in names {
+            let definition = &spm_toml.extensions[name];
+            match spm_lock.extensions.get(name) {
+                None => problems.push(format!("{name}: in spm.toml, but not in spm.lock")),
+                Some(lock) => {
+                    if let Some(problem) = lock_mismatch(definition, lock) {
+                        problems.push(format!("{name}: {problem}"));
+                    }
+                }
+            }
+        }
+        for name in spm_lock.extensions.keys() {
+            if !spm_toml.extensions.contains_key(name) {
+                problems.push(format!("{name}: in spm.lock, but not in spm.toml"));
+            }
+        }
+        // older lockfiles don't have a fingerprint
+        if problems.is_empty()
+            && !spm_lock.fingerprint.is_empty()
+            && spm_lock.fingerprint != spm_toml.fingerprint()?
+        {
+            problems.push("the extensions in spm.toml changed since spm.lock was generated".into());
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "spm.toml and spm.lock are out of sync, run `spm install` to update spm.lock:\n  {}",
+            problems.join("\n  ")
+        ))
+    }
+
+    /// Implements `spm update`
+    pub fn command_update(&self, packages: &[String], latest: bool) -> Result<()> {
+        let updates = self.update_lockfile(packages, latest)?;
+        for (name, old, new) in &updates {
+            match (old, new) {
+                (None, None) => println!("{name}"),
+                (None, Some(new)) => println!("{name}: {new}"),
+                (Some(old), Some(new)) if old != new => println!("{name}: {old} -> {new}"),
+                (Some(old), _) => println!("{name}: {old} (up to date)"),
+            }
+        }
         self.install(None)?;
         Ok(())
     }
 
+    /// Re-resolve the given extensions (or all of them, if none are given) to their newest
+    /// allowed version, keeping every other spm.lock entry as is. With `latest`, the
+    /// constraints in spm.toml are first bumped to the latest version. Returns the old and
+    /// new locked version of each re-resolved extension.
+    fn update_lockfile(&self, packages: &[String], latest: bool) -> Result<Vec<VersionUpdate>> {
+        self.ensure_unlocked("spm update")?;
+        let config = self.load_config()?;
+        let mut spm_toml = self.read_spm_toml()?;
+        if let Some(name) = packages
+            .iter()
+            .find(|name| !spm_toml.extensions.contains_key(*name))
+        {
+            return Err(anyhow!("{name} is not an extension in spm.toml"));
+        }
+        let selected = |name: &str| packages.is_empty() || packages.iter().any(|p| p == name);
+
+        if latest {
+            let mut doc = self
+                .read_spm_toml_contents()?
+                .parse::<Document>()
+                .context("invalid spm.toml")?;
+            for (name, definition) in &spm_toml.extensions {
+                let constraint = match definition.version() {
+                    Some(constraint) if selected(name) => constraint,
+                    _ => continue,
+                };
+                let pkg_resolver =
+                    self.definition_package_resolver(name, definition, false, &config)?;
+                let constraint = updated_constraint(
+                    constraint,
+                    &definition_latest_version(pkg_resolver.as_ref(), definition)?,
+                );
+                match definition {
+                    SpmTomlExtensionDefinition::Version(_) => {
+                        doc["extensions"][name.as_str()] = value(constraint)
+                    }
+                    _ => doc["extensions"][name.as_str()]["version"] = value(constraint),
+                }
+            }
+            self.write_spm_toml_contents(doc.to_string())?;
+            spm_toml = self.read_spm_toml()?;
+        }
+
+        let previous = match self.spm_lock_path.exists() {
+            true => self.read_spm_lock()?,
+            false => SpmLock::default(),
+        };
+        let mut names: Vec<&String> = spm_toml.extensions.keys().collect();
+        names.sort();
+        let mut updates = vec![];
+        let mut extensions = BTreeMap::new();
+        for name in names {
+            let old = previous.extensions.get(name);
+            let lock = match old {
+                Some(old) if !selected(name) => old.clone(),
+                _ => {
+                    let lock =
+                        self.lock_extension(name, &spm_toml.extensions[name], &config, old)?;
+                    updates.push((
+                        name.clone(),
+                        old.and_then(|old| old.version()).map(String::from),
+                        lock.version().map(String::from),
+                    ));
+                    lock
+                }
+            };
+            extensions.insert(name.clone(), lock);
+        }
+        self.write_spm_lock(SpmLock {
+            version: 0,
+            fingerprint: spm_toml.fingerprint()?,
+            extensions,
+        })?;
+        Ok(updates)
+    }
+
+    /// Implements `spm outdated`. Returns whether any extension has a newer version.
+    pub fn command_outdated(&self, prerelease: bool, changelog: bool) -> Result<bool> {
+        let outdated = self.outdated(prerelease, changelog)?;
+        if outdated.is_empty() {
+            println!("All extensions are up to date");
+            return Ok(false);
+        }
+        println!("name\tcurrent\twanted\tlatest");
+        for extension in &outdated {
+            println!(
+                "{}\t{}\t{}\t{}",
+                extension.name,
+                extension.current,
+                extension.wanted.as_deref().unwrap_or("-"),
+                extension.latest
+            );
+            if let Some(notes) = &extension.notes {
+                let mut lines = notes.lines().filter(|line| !line.trim().is_empty());
+                for line in lines.by_ref().take(CHANGELOG_EXCERPT_LINES) {
+                    println!("    {}", line.trim_end());
+                }
+                if lines.next().is_some() {
+                    println!("    ...");
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Compare every versioned extension in spm.lock against the newest version its
+    /// constraint allows and the latest published version, returning the ones behind
+    fn outdated(&self, prerelease: bool, changelog: bool) -> Result<Vec<OutdatedExtension>> {
+        let config = self.load_config()?;
+        let spm_toml = self.read_spm_toml()?;
+        let spm_lock = self.read_spm_lock()?;
+        let mut names: Vec<&String> = spm_lock.extensions.keys().collect();
+        names.sort();
+        let mut outdated = vec![];
+        for name in names {
+            let (current, definition) = match (
+                spm_lock.extensions[name].version(),
+                spm_toml.extensions.get(name),
+            ) {
+                (Some(current), Some(definition)) => (current, definition),
+                _ => continue,
+            };
+            let pkg_resolver =
+                self.definition_package_resolver(name, definition, prerelease, &config)?;
+            let latest = match definition_latest_version(pkg_resolver.as_ref(), definition) {
+                Ok(latest) => latest,
+                Err(err) => {
+                    eprintln!("Could not check {name} for updates: {err:#}");
+                    continue;
+                }
+            };
+            // only what `spm update` would install, so --pre-release doesn't widen it
+            let wanted = match resolve_version_range(pkg_resolver.as_ref(), definition, false)
+                .and_then(|definition| definition.required_version(name))
+            {
+                Ok(wanted) => Some(wanted),
+                Err(err) => {
+                    eprintln!("Could not resolve the wanted version of {name}: {err:#}");
+                    None
+                }
+            };
+            if wanted.as_deref() == Some(current) && current == latest {
+                continue;
+            }
+            let notes = match changelog {
+                true => pkg_resolver.release_notes(&latest).unwrap_or_else(|err| {
+                    eprintln!("Could not fetch release notes of {name} {latest}: {err:#}");
+                    None
+                }),
+                false => None,
+            };
+            outdated.push(OutdatedExtension {
+                name: name.clone(),
+                current: current.to_owned(),
+                wanted,
+                latest,
+                notes,
+            });
+        }
+        Ok(outdated)
+    }
+
+    /// Implements `spm versions`
+    pub fn command_versions(&self, reference: &str, json: bool) -> Result<()> {
+        let versions = self.versions(reference)?;
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&versions).context("Failed to serialize versions")?
+            );
+            return Ok(());
+        }
+        println!("version\tprerelease\tspm.json\tplatforms");
+        for version in &versions {
+            println!(
+                "{}\t{}\t{}\t{}",
+                version.version,
+                if version.prerelease { "yes" } else { "no" },
+                match version.spm_json {
+                    Some(true) => "yes",
+                    Some(false) => "no",
+                    None => "-",
+                },
+                version.platforms.join(",")
+            );
+        }
+        Ok(())
+    }
+
+    /// Every published version of a package, newest first. The package is either an
+    /// extension in spm.toml, or a reference like the ones `spm add` takes.
+    fn versions(&self, reference: &str) -> Result<Vec<PackageVersion>> {
+        let config = self.load_config()?;
+        let definition = match self.spm_toml_exists() {
+            true => self.read_spm_toml()?.extensions.remove(reference),
+            false => None,
+        };
+        let pkg_resolver = match &definition {
+            Some(definition) => {
+                self.definition_package_resolver(reference, definition, true, &config)?
+            }
+            None => determine_package_resolver(reference, true, &config)?,
+        };
+        let mut versions = pkg_resolver.list_versions()?;
+        // versions that aren't semver keep the resolver's order, after the rest
+        versions.sort_by_cached_key(|version| {
+            std::cmp::Reverse(semver::Version::parse(version.version.trim_start_matches('v')).ok())
+        });
+        Ok(versions)
+    }
+
+    /// Implements `spm search`
+    pub fn command_search(&self, term: &str) -> Result<()> {
+        let config = self.load_config()?;
+        let registries = crate::index::registries(&config)?;
+        if registries.is_empty() {
+            return Err(anyhow!(
+                "No package index configured, set `[index] url` or `[registries]` in the spm config, or SPM_INDEX"
+            ));
+        }
+        let mut found = false;
+        for registry in &registries {
+            for entry in crate::index::search(&registry.url, term)? {
+                found = true;
+                if registries.len() > 1 {
+                    println!("{}\t{}\t({})", entry.name, entry.description, registry.name);
+                } else {
+                    println!("{}\t{}", entry.name, entry.description);
+                }
+            }
+        }
+        if !found {
+            println!("No packages found matching \"{term}\"");
+        }
+        Ok(())
+    }
+
     /// Implements `spm activate`
     pub fn command_activate(&self) -> Result<()> {
         let library_path = self.resolve_library_path()?;
@@ -186,36 +528,160 @@ impl Project {
             self.create_sqlite_extensions_dir()?;
         }
 
+        self.load_config()?;
         let spm_lock: SpmLock = self.read_spm_lock()?;
         for extension in spm_lock.extensions.values() {
-            match extension {
-                SpmLockExtension::GithubRelease(extension) => {
-                    extension.download_platform(platform.clone(), self)?;
-                }
-            }
+            extension.install_platform(platform.clone(), self)?;
         }
         Ok(())
     }
 
-    // don't regenerate lockfile from scratch every time
+    /// Bring spm.lock in line with spm.toml. Entries that still satisfy their definition
+    /// are kept as is, so only new or changed extensions are resolved again, and entries
+    /// of removed extensions are dropped. spm.lock isn't rewritten if nothing changed,
+    /// and with `--locked` it's an error if anything would.
     fn generate_lockfile(&self) -> Result<()> {
         let spm_toml = self.read_spm_toml()?;
-        let mut extensions = HashMap::new();
+        let config = self.load_config()?;
+        let previous = match self.spm_lock_path.exists() {
+            true => Some(self.read_spm_lock()?),
+            false => None,
+        };
+        let mut extensions = BTreeMap::new();
         for (extension_name, definition) in &spm_toml.extensions {
-            // TODO change, because won't this get the latest version of the defined extension?
-            let pkg_resolver = determine_package_resolver(extension_name, false)?;
-            let lock = pkg_resolver.generate_lock(definition)?;
+            let previous = previous
+                .as_ref()
+                .and_then(|previous| previous.extensions.get(extension_name));
+            let lock = match previous {
+                Some(previous) if lock_satisfies(definition, previous) => previous.clone(),
+                // with --locked, local extensions keep their entry instead of being re-read
+                Some(previous) if self.locked && lock_mismatch(definition, previous).is_none() => {
+                    previous.clone()
+                }
+                _ => {
+                    self.ensure_unlocked(&format!("Locking {extension_name}"))?;
+                    self.lock_extension(extension_name, definition, &config, previous)?
+                }
+            };
             extensions.insert(extension_name.clone(), lock);
         }
-        self.write_spm_lock(SpmLock {
+        let lock = SpmLock {
             version: 0,
+            fingerprint: spm_toml.fingerprint()?,
             extensions,
-        })?;
+        };
+        match previous {
+            Some(previous) if previous == lock => (),
+            // with --locked only the entries have to match, so edits that they still satisfy
+            // and lockfiles from before fingerprints are accepted as is
+            Some(previous) if self.locked && previous.extensions == lock.extensions => (),
+            _ => {
+                let removed: Vec<&str> = previous
+                    .iter()
+                    .flat_map(|previous| previous.extensions.keys())
+                    .filter(|name| !lock.extensions.contains_key(*name))
+                    .map(String::as_str)
+                    .collect();
+                let operation = match (&previous, removed.is_empty()) {
+                    (None, _) => "Creating spm.lock".to_owned(),
+                    (Some(_), false) => format!("Removing {}", removed.join(", ")),
+                    // definitions were edited, but their entries still satisfy them
+                    (Some(_), true) => "Recording edited spm.toml definitions".to_owned(),
+                };
+                self.ensure_unlocked(&operation)?;
+                self.write_spm_lock(lock)?
+            }
+        }
         Ok(())
     }
 
+    /// Resolve an extension defined in spm.toml to a new spm.lock entry. `previous` is
+    /// its entry in the current spm.lock, if any.
+    fn lock_extension(
+        &self,
+        name: &str,
+        definition: &SpmTomlExtensionDefinition,
+        config: &SpmConfig,
+        previous: Option<&SpmLockExtension>,
+    ) -> Result<SpmLockExtension> {
+        let pkg_resolver = self.definition_package_resolver(name, definition, false, config)?;
+        let definition = &resolve_version_range(pkg_resolver.as_ref(), definition, false)?;
+        let mut lock = pkg_resolver.generate_lock(definition)?;
+        lock.map_urls(crate::http::lock_url);
+        if let Some(previous) = previous {
+            check_registry_unchanged(name, definition, previous, &lock)?;
+            check_integrity_unchanged(name, previous, &lock)?;
+        }
+        Ok(lock)
+    }
+
+    /// Read the user config, overlayed with any project-level settings from spm.toml,
+    /// and register its tokens and mirror rules with the HTTP client
+    pub(crate) fn load_config(&self) -> Result<SpmConfig> {
+        let (config, tokens) = self.project_config(SpmConfig::load_user_config()?)?;
+        crate::http::set_tokens(tokens);
+        crate::http::set_mirrors(config.mirror.clone().unwrap_or_default());
+        Ok(config)
+    }
+
+    /// Overlay spm.toml's settings on the user config. The (host, token) pairs are taken
+    /// from the user config before merging, so spm.toml can't point a token at another host.
+    fn project_config(&self, mut config: SpmConfig) -> Result<(SpmConfig, Vec<(String, String)>)> {
+        let tokens = config.tokens();
+        if self.spm_toml_exists() {
+            let spm_toml = self.read_spm_toml()?;
+            config.merge(
+                spm_toml.hosts,
+                spm_toml.registries,
+                &self.base_project_directory,
+            );
+        }
+        Ok((config, tokens))
+    }
+
+    /// Determine the resolver for an extension defined in spm.toml. Local extensions are
+    /// resolved from their `path`, inline URL extensions from their URLs, and everything
+    /// else from the extension name.
+    fn definition_package_resolver(
+        &self,
+        name: &str,
+        definition: &SpmTomlExtensionDefinition,
+        prerelease: bool,
+        config: &SpmConfig,
+    ) -> Result<Box<dyn PackageResolver>> {
+        let prerelease = prerelease || definition.prerelease();
+        match definition {
+            SpmTomlExtensionDefinition::Local { path, .. } => Ok(Box::new(LocalPackage::new(
+                name,
+                path,
+                self.resolve_path(path),
+            ))),
+            SpmTomlExtensionDefinition::Url { .. } | SpmTomlExtensionDefinition::Urls { .. } => {
+                Ok(Box::new(UrlPackage::new(
+                    name,
+                    definition.url_platforms().unwrap_or_default(),
+                )))
+            }
+            SpmTomlExtensionDefinition::Definition {
+                registry: Some(registry),
+                ..
+            } => Ok(Box::new(IndexPackage::parse_in_registry(
+                name,
+                Some(registry),
+                prerelease,
+                config,
+            )?)),
+            _ => determine_package_resolver(name, prerelease, config),
+        }
+    }
+
+    /// resolve a path from spm.toml, relative to the project directory
+    pub(crate) fn resolve_path(&self, path: &str) -> PathBuf {
+        self.base_project_directory.join(path)
+    }
+
     // full path of $BASE/sqlite_extensions/
-    fn sqlite_extensions_path(&self) -> std::path::PathBuf {
+    pub(crate) fn sqlite_extensions_path(&self) -> std::path::PathBuf {
         self.sqlite_extensions_path.clone()
     }
 
@@ -308,10 +774,25 @@ pub struct SpmToml {
     /// env var. Useful for loading sqlite extensions that exist in a user's filesystem
     /// that isn't managed by spm directly.
     pub preload_directories: Option<Vec<String>>,
+    /// Project-level settings for self-hosted forges, see [`SpmConfig::hosts`]
+    pub hosts: Option<HashMap<String, HostConfig>>,
+    /// Project-level package indexes, see [`SpmConfig::registries`]
+    pub registries: Option<HashMap<String, RegistryConfig>>,
     /// Table of SQLite extensions to download.
     pub extensions: HashMap<String, SpmTomlExtensionDefinition>,
 }
 
+impl SpmToml {
+    /// Hex-encoded sha256 of the extension definitions, which changes whenever an
+    /// extension is added, removed or edited, but not with formatting or comments
+    pub fn fingerprint(&self) -> Result<String> {
+        let extensions: BTreeMap<&String, &SpmTomlExtensionDefinition> =
+            self.extensions.iter().collect();
+        let contents = serde_json::to_vec(&extensions).context("Failed to serialize spm.toml")?;
+        Ok(hex::encode(Sha256::digest(contents)))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 /// Definition of an extension in spm.toml, either a version string or object.
@@ -331,16 +812,148 @@ pub enum SpmTomlExtensionDefinition {
         version: String,
         /// List of specific artifacts to download. If not provided, all artifacts
         artifacts: Option<Vec<String>>,
+        /// Name of the registry to resolve a short name through, instead of the
+        /// first registry that has it
+        registry: Option<String>,
+        /// Whether pre-release versions can be resolved
+        prerelease: Option<bool>,
+        /// Limits pre-releases to ones in a channel, ex `beta` for `v1.0.0-beta.2`.
+        /// Implies `prerelease = true`.
+        channel: Option<String>,
+    },
+    /// An extension on the local filesystem, either a directory with a spm.json
+    /// or a single .tar.gz/.zip archive. Relative paths are relative to spm.toml. Example:
+    /// `project = { path = "../my-ext/dist" }`
+    Local {
+        /// Path to a directory with a spm.json, or to a .tar.gz/.zip archive
+        path: String,
+        /// List of specific artifacts to install. If not provided, all artifacts
+        artifacts: Option<Vec<String>>,
+    },
+    /// A single artifact at a URL, pinned by its sha256, for extensions that don't
+    /// publish a spm.json. Example:
+    /// `vec = { url = "https://…/vec0-linux-x86_64.tar.gz", sha256 = "…", os = "linux", cpu = "x86_64" }`
+    Url {
+        /// URL of a .tar.gz/.zip archive or a loadable library
+        url: String,
+        /// Hex-encoded sha256 checksum of the artifact
+        sha256: String,
+        /// Platform of the artifact, inferred from the file name if not provided
+        os: Option<String>,
+        cpu: Option<String>,
+        /// List of specific artifacts to install. If not provided, all artifacts
+        artifacts: Option<Vec<String>>,
+    },
+    /// Artifacts for several platforms at URLs, each pinned by its sha256. Example:
+    /// `vec = { platforms = [{ url = "…", sha256 = "…", os = "linux", cpu = "x86_64" }] }`
+    Urls {
+        platforms: Vec<SpmTomlUrlPlatform>,
+        /// List of specific artifacts to install. If not provided, all artifacts
+        artifacts: Option<Vec<String>>,
     },
 }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A single platform's artifact of an extension defined by URLs in spm.toml
+pub struct SpmTomlUrlPlatform {
+    /// URL of a .tar.gz/.zip archive or a loadable library
+    pub url: String,
+    /// Hex-encoded sha256 checksum of the artifact
+    pub sha256: String,
+    /// Platform of the artifact, inferred from the file name if not provided
+    pub os: Option<String>,
+    pub cpu: Option<String>,
+}
+
+impl SpmTomlExtensionDefinition {
+    /// The version string of the extension, if one is declared
+    pub fn version(&self) -> Option<&str> {
+        match self {
+            SpmTomlExtensionDefinition::Version(version) => Some(version),
+            SpmTomlExtensionDefinition::Definition { version, .. } => Some(version),
+            SpmTomlExtensionDefinition::Local { .. }
+            | SpmTomlExtensionDefinition::Url { .. }
+            | SpmTomlExtensionDefinition::Urls { .. } => None,
+        }
+    }
+    /// The subset of artifacts to install, if declared
+    pub fn artifacts(&self) -> Option<Vec<String>> {
+        match self {
+            SpmTomlExtensionDefinition::Version(_) => None,
+            SpmTomlExtensionDefinition::Definition { artifacts, .. } => artifacts.clone(),
+            SpmTomlExtensionDefinition::Local { artifacts, .. }
+            | SpmTomlExtensionDefinition::Url { artifacts, .. }
+            | SpmTomlExtensionDefinition::Urls { artifacts, .. } => artifacts.clone(),
+        }
+    }
+    /// Whether pre-release versions can be resolved, with `prerelease = true` or a `channel`
+    pub fn prerelease(&self) -> bool {
+        match self {
+            SpmTomlExtensionDefinition::Definition {
+                prerelease,
+                channel,
+                ..
+            } => *prerelease == Some(true) || channel.is_some(),
+            _ => false,
+        }
+    }
+    /// The pre-release channel that versions are limited to, if any
+    pub fn channel(&self) -> Option<&str> {
+        match self {
+            SpmTomlExtensionDefinition::Definition { channel, .. } => channel.as_deref(),
+            _ => None,
+        }
+    }
+    /// The artifacts of a `url` or `platforms` definition
+    pub fn url_platforms(&self) -> Option<Vec<SpmTomlUrlPlatform>> {
+        match self {
+            SpmTomlExtensionDefinition::Url {
+                url,
+                sha256,
+                os,
+                cpu,
+                ..
+            } => Some(vec![SpmTomlUrlPlatform {
+                url: url.clone(),
+                sha256: sha256.clone(),
+                os: os.clone(),
+                cpu: cpu.clone(),
+            }]),
+            SpmTomlExtensionDefinition::Urls { platforms, .. } => Some(platforms.clone()),
+            _ => None,
+        }
+    }
+    /// A copy of this definition with its version replaced, ex to pin a range to a tag
+    pub fn with_version(&self, version: String) -> SpmTomlExtensionDefinition {
+        let mut definition = self.clone();
+        match &mut definition {
+            SpmTomlExtensionDefinition::Version(v)
+            | SpmTomlExtensionDefinition::Definition { version: v, .. } => *v = version,
+            SpmTomlExtensionDefinition::Local { .. }
+            | SpmTomlExtensionDefinition::Url { .. }
+            | SpmTomlExtensionDefinition::Urls { .. } => (),
+        }
+        definition
+    }
+    /// The version string of the extension, or an error naming the extension if missing
+    pub fn required_version(&self, name: &str) -> Result<String> {
+        self.version()
+            .map(|version| version.to_owned())
+            .ok_or_else(|| anyhow!("A version is required for {name} in spm.toml"))
+    }
+}
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 /// Definition of the spm.lock file, which serializes to JSON
 pub struct SpmLock {
     /// Always 0 for now, in beta
     pub version: i32,
-    /// Downloaded and checksums for specified extensiond
-    pub extensions: HashMap<String, SpmLockExtension>,
+    /// Hash of the extensions in spm.toml that this lockfile was generated from, see
+    /// [`SpmToml::fingerprint`]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub fingerprint: String,
+    /// Downloaded and checksums for specified extensiond, sorted by name
+    pub extensions: BTreeMap<String, SpmLockExtension>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -350,6 +963,85 @@ pub struct SpmLock {
 pub enum SpmLockExtension {
     /// URLs and checksums of an spm extension distributed on Github Releases
     GithubRelease(GithubReleaseExtension),
+    /// Object URLs and ETags of an spm extension stored in an S3-compatible bucket.
+    /// Comes before `Https`, which would also match its fields.
+    S3(S3Extension),
+    /// URLs and checksums of an spm extension hosted on a plain HTTP(S) server,
+    /// or on another forge or registry that links to plain URLs
+    Https(HttpsExtension),
+    /// Manifest and layer digests of an extension published as an OCI artifact
+    Oci(OciExtension),
+    /// Path and checksums of an extension on the local filesystem
+    Local(LocalExtension),
+}
+
+impl SpmLockExtension {
+    /// Download or copy the entry's asset for the given platform into sqlite_extensions/
+    pub(crate) fn install_platform(&self, platform: Platform, project: &Project) -> Result<()> {
+        match self {
+            SpmLockExtension::GithubRelease(extension) => {
+                extension.download_platform(platform, project)
+            }
+            SpmLockExtension::Https(extension) => extension.download_platform(platform, project),
+            SpmLockExtension::S3(extension) => extension.download_platform(platform, project),
+            SpmLockExtension::Oci(extension) => extension.download_platform(platform, project),
+            SpmLockExtension::Local(extension) => extension.install_platform(platform, project),
+        }
+    }
+
+    /// The subset of artifacts that the entry was locked with, if declared
+    pub fn artifacts(&self) -> Option<&Vec<String>> {
+        match self {
+            SpmLockExtension::GithubRelease(extension) => extension.artifacts.as_ref(),
+            SpmLockExtension::S3(extension) => extension.artifacts.as_ref(),
+            SpmLockExtension::Https(extension) => extension.artifacts.as_ref(),
+            SpmLockExtension::Oci(extension) => extension.artifacts.as_ref(),
+            SpmLockExtension::Local(extension) => extension.artifacts.as_ref(),
+        }
+    }
+
+    /// The locked version, or None for extensions without versions
+    pub fn version(&self) -> Option<&str> {
+        let version = match self {
+            SpmLockExtension::GithubRelease(extension) => &extension.version,
+            SpmLockExtension::S3(extension) => &extension.version,
+            SpmLockExtension::Https(extension) => &extension.version,
+            SpmLockExtension::Oci(extension) => &extension.version,
+            SpmLockExtension::Local(_) => return None,
+        };
+        Some(version.as_str()).filter(|version| !version.is_empty())
+    }
+
+    /// Rewrite the spm.json and asset URLs recorded for this extension
+    fn map_urls(&mut self, f: impl Fn(&str) -> String) {
+        let assets = match self {
+            SpmLockExtension::GithubRelease(extension) => {
+                // resolved_url is kept canonical, since it identifies the repository and
+                // its API host, ex for private releases. Asset URLs are built from it at
+                // install time, and go through the mirror rules like any other request.
+                extension.resolved_spm_json = f(&extension.resolved_spm_json);
+                return;
+            }
+            SpmLockExtension::S3(extension) => {
+                extension.resolved_spm_json = f(&extension.resolved_spm_json);
+                &mut extension.resolved_assets
+            }
+            SpmLockExtension::Https(extension) => {
+                if let Some(url) = &mut extension.resolved_spm_json {
+                    *url = f(url);
+                }
+                &mut extension.resolved_assets
+            }
+            SpmLockExtension::Oci(extension) => {
+                extension.resolved_manifest = f(&extension.resolved_manifest);
+                &mut extension.resolved_assets
+            }
+            SpmLockExtension::Local(_) => return,
+        };
+        for url in assets.values_mut() {
+            *url = f(url);
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -386,7 +1078,7 @@ pub struct SpmPackageJsonPlatform {
 }
 
 /// (os, cpu)
-type Platform = Option<(String, String)>;
+pub type Platform = Option<(String, String)>;
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 /// A SQLite extension project that's published and distributed with Github Releases
@@ -401,120 +1093,439 @@ pub struct GithubReleaseExtension {
     /// Full URL to the published spm.json for this release
     #[serde(rename = "resolved_spm_json")]
     pub resolved_spm_json: String,
-    /// TODO some type of checksum
+    /// Subresource Integrity hash of the fetched spm.json, ex `sha256-<base64>`. Empty
+    /// when spm.json was inferred, or in older lockfiles.
     pub integrity: String,
     /// The contents of spm.json for this release
     #[serde(rename = "spm_json")]
     pub spm_json: SpmPackageJson,
+    /// Whether spm_json was inferred from the release's assets, because the release
+    /// didn't publish a spm.json
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub inferred: bool,
 }
 
 impl GithubReleaseExtension {
-    fn should_install(&self, name: &str) -> bool {
-        let p = std::path::Path::new(name);
-        let p = p
-            .file_stem()
-            .map_or(name.to_string(), |x| x.to_string_lossy().to_string());
-        match &self.artifacts {
-            Some(artifacts) => artifacts.iter().any(|a| *a == p),
-            None => true,
-        }
-    }
     /// Download the extension for the given platform (MacOS x86_64, Linux x86_64, etc.)
     /// and save
     pub(crate) fn download_platform(&self, platform: Platform, project: &Project) -> Result<()> {
-        let (os, arch) = match platform {
-            Some((os, arch)) => (os, arch),
-            None => (
-                std::env::consts::OS.to_owned(),
-                std::env::consts::ARCH.to_owned(),
-            ),
-        };
-        // if the extension definition only declares a subset of artifacts, then only
-        // download those. ex `"xxx" = {artifacts=["a", "c"]}`, only download a and c, not b
-        //if let Some(artifacts) = &self.artifacts {
-        //    if !artifacts.iter().any(|a| *a == *name) {
-        //        return Ok(());
-        //    }
-        //}
-        let platform = self
-            .spm_json
-            .loadable
+        let platform = find_loadable_platform(&self.spm_json, platform)?;
+        let api = self.release_api(project)?;
+        if crate::http::has_token(&api.api_url) {
+            // the releases/download/ URLs don't work for private repos, so
+            // authenticated requests go through the API asset endpoint
+            let release = api.fetch_release(&self.version)?;
+            let asset = release.asset(&platform.asset_name)?;
+            return download_asset_request(
+                asset.api_request()?,
+                platform,
+                &self.artifacts,
+                false,
+                project,
+            );
+        }
+        let url = format!(
+            "{}/releases/download/{}/{}",
+            self.resolved_url, self.version, platform.asset_name
+        );
+        download_asset(&url, platform, &self.artifacts, false, project)
+    }
+
+    /// The Github API for the repository this extension was resolved from
+    fn release_api(&self, project: &Project) -> Result<GithubReleasePackage> {
+        let url = Url::parse(&self.resolved_url)
+            .with_context(|| format!("invalid resolved_url in spm.lock: {}", self.resolved_url))?;
+        let host = host_key(&url).ok_or_else(|| anyhow!("{url} has no host"))?;
+        let path_segments = url
+            .path_segments()
+            .ok_or_else(|| anyhow!("{url} is not a repository URL"))?;
+        github_parse_path(&host, path_segments, false, &project.load_config()?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// A single release, as returned by the Github releases API
+struct GithubRelease {
+    tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    /// Release notes, in markdown
+    body: Option<String>,
+    #[serde(default)]
+    assets: Vec<GithubReleaseAsset>,
+}
+
+impl GithubRelease {
+    fn asset(&self, name: &str) -> Result<&GithubReleaseAsset> {
+        self.assets
             .iter()
-            .find(|platform| platform.os == os && platform.cpu == arch);
-        let platform = platform.ok_or_else(|| {
-            anyhow!("No matching platform found for the current device ({os}-{arch})")
-        })?;
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| anyhow!("Release {} has no {name} asset", self.tag_name))
+    }
+}
 
-        let asset_name = &platform.asset_name;
-        let url = format!(
-            "{}/releases/download/{}/{asset_name}",
-            self.resolved_url, self.version
+#[derive(Debug, Deserialize)]
+/// A single release asset, as returned by the Github releases API
+struct GithubReleaseAsset {
+    name: String,
+    /// API URL of the asset, which works for private repos
+    url: String,
+    /// Public download URL of the asset
+    browser_download_url: String,
+    /// Checksum of the asset, ex `sha256:abc...`. Only on newer releases.
+    digest: Option<String>,
+}
+
+impl GithubReleaseAsset {
+    /// Request for the contents of the asset through the API
+    fn api_request(&self) -> Result<ureq::Request> {
+        Ok(crate::http::http_get(&self.url)?.set("Accept", "application/octet-stream"))
+    }
+    /// Request for the contents of the asset, through the API when authenticated
+    fn request(&self) -> Result<ureq::Request> {
+        if crate::http::has_token(&self.url) {
+            self.api_request()
+        } else {
+            crate::http::http_get(&self.browser_download_url)
+        }
+    }
+}
+
+/// Read the contents of an http(s) or file:// URL, or a local file
+pub(crate) fn read_location(location: &str) -> Result<Vec<u8>> {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        return read_response(crate::http::http_get(location)?);
+    }
+    let path = match Url::parse(location) {
+        Ok(url) if url.scheme() == "file" => url
+            .to_file_path()
+            .map_err(|_| anyhow!("Invalid file URL: {location}"))?,
+        _ => PathBuf::from(location),
+    };
+    std::fs::read(&path).with_context(|| format!("Could not read {}", path.display()))
+}
+
+/// Whether reading a location failed because there is nothing there, an HTTP 404 or a
+/// missing file, rather than because the server or filesystem couldn't be read
+pub(crate) fn is_not_found(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<ureq::Error>(),
+            Some(ureq::Error::Status(404, _))
+        ) || cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound)
+    })
+}
+
+/// Read the full body of a request
+pub(crate) fn read_response(request: ureq::Request) -> Result<Vec<u8>> {
+    let url = request.url().to_owned();
+    let mut contents = Vec::new();
+    request
+        .call()
+        .with_context(|| format!("Error making request to {url}"))?
+        .into_reader()
+        .read_to_end(&mut contents)
+        .with_context(|| format!("Error reading response from {url}"))?;
+    Ok(contents)
+}
+
+/// Whether a release asset is a list of checksums, like `SHA256SUMS` or `checksums.txt`
+fn is_checksums_asset(name: &str) -> bool {
+    matches!(
+        name.to_lowercase().as_str(),
+        "sha256sums" | "sha256sums.txt" | "checksums.txt" | "checksums.sha256"
+    )
+}
+
+/// Parse the output of `sha256sum`, lines of `<hex checksum>  <file name>`, into a map
+/// of file name to checksum.
+fn parse_checksums(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (checksum, name) = line.trim().split_once(char::is_whitespace)?;
+            // sha256sum prefixes names with '*' in binary mode
+            let name = name.trim().trim_start_matches('*');
+            (checksum.len() == 64).then(|| (name.to_owned(), checksum.to_lowercase()))
+        })
+        .collect()
+}
+
+/// Infer the (os, cpu) of a release asset from common naming conventions, like
+/// `sqlite-foo-v0.1.0-linux-x86_64.tar.gz` or `foo_darwin_arm64.zip`.
+pub(crate) fn infer_platform(asset_name: &str) -> Option<(String, String)> {
+    let name = asset_name.to_lowercase().replace("x86_64", "x64");
+    let tokens: Vec<&str> = name.split(|c: char| !c.is_ascii_alphanumeric()).collect();
+    let os = tokens.iter().find_map(|token| match *token {
+        "linux" | "manylinux" | "musllinux" => Some("linux"),
+        "macos" | "darwin" | "osx" | "apple" | "mac" => Some("macos"),
+        "windows" | "win" | "win64" | "mingw" | "msvc" => Some("windows"),
+        _ => None,
+    })?;
+    let cpu = tokens.iter().find_map(|token| match *token {
+        "x64" | "amd64" => Some("x86_64"),
+        "aarch64" | "arm64" => Some("aarch64"),
+        _ => None,
+    })?;
+    Some((os.to_owned(), cpu.to_owned()))
+}
+
+/// Find the loadable entry in spm.json for the given platform, defaulting to the
+/// current device when no platform is provided.
+pub(crate) fn find_loadable_platform(
+    spm_json: &SpmPackageJson,
+    platform: Platform,
+) -> Result<&SpmPackageJsonPlatform> {
+    let (os, arch) = match platform {
+        Some((os, arch)) => (os, arch),
+        None => (
+            std::env::consts::OS.to_owned(),
+            std::env::consts::ARCH.to_owned(),
+        ),
+    };
+    spm_json
+        .loadable
+        .iter()
+        .find(|platform| platform.os == os && platform.cpu == arch)
+        .ok_or_else(|| anyhow!("No matching platform found for the current device ({os}-{arch})"))
+}
+
+/// if the extension definition only declares a subset of artifacts, then only
+/// install those. ex `"xxx" = {artifacts=["a", "c"]}`, only install a and c, not b
+fn should_install(artifacts: &Option<Vec<String>>, name: &str) -> bool {
+    let p = std::path::Path::new(name);
+    let p = p
+        .file_stem()
+        .map_or(name.to_string(), |x| x.to_string_lossy().to_string());
+    match artifacts {
+        Some(artifacts) => artifacts.contains(&p),
+        None => true,
+    }
+}
+
+/// Download the asset at `url`, verify it against the sha256 checksum declared
+/// in spm.json, and unpack it into the sqlite_extensions/ directory.
+pub(crate) fn download_asset(
+    url: &str,
+    platform: &SpmPackageJsonPlatform,
+    artifacts: &Option<Vec<String>>,
+    loadables_only: bool,
+    project: &Project,
+) -> Result<()> {
+    // assets of packages in a local package index
+    if url.starts_with("file://") {
+        println!("reading {url} ...");
+        let contents = read_location(url)?;
+        verify_sha256(&platform.asset_name, &contents, &platform.asset_sha256)?;
+        return unpack_asset(
+            &platform.asset_name,
+            contents,
+            artifacts,
+            loadables_only,
+            project,
         );
-        println!("downloading {url} ...");
-        let mut asset = crate::http::http_get(url.as_str())
-            .call()
-            .with_context(|| format!("Error making request to {url}"))?
-            .into_reader();
+    }
+    download_asset_request(
+        crate::http::http_get(url)?,
+        platform,
+        artifacts,
+        loadables_only,
+        project,
+    )
+}
 
-        let mut asset_buffer = Vec::new();
-        asset.read_to_end(&mut asset_buffer)?;
+/// Same as [`download_asset`], for requests that need extra headers
+pub(crate) fn download_asset_request(
+    request: ureq::Request,
+    platform: &SpmPackageJsonPlatform,
+    artifacts: &Option<Vec<String>>,
+    loadables_only: bool,
+    project: &Project,
+) -> Result<()> {
+    let url = request.url().to_owned();
+    println!("downloading {url} ...");
+    let mut asset = request
+        .call()
+        .with_context(|| format!("Error making request to {url}"))?
+        .into_reader();
+
+    let mut asset_buffer = Vec::new();
+    asset.read_to_end(&mut asset_buffer)?;
+
+    verify_sha256(&platform.asset_name, &asset_buffer, &platform.asset_sha256)?;
+    unpack_asset(
+        &platform.asset_name,
+        asset_buffer,
+        artifacts,
+        loadables_only,
+        project,
+    )
+}
+
+/// Verify that the hex-encoded sha256 checksum of an asset matches the expected one.
+pub(crate) fn verify_sha256(asset_name: &str, contents: &[u8], expected: &str) -> Result<()> {
+    let asset_hash = hex::encode(Sha256::digest(contents));
+    if asset_hash != expected {
+        return Err(anyhow!(
+            "sha256 checksum mismatch for {asset_name}: expected {expected}, got {asset_hash}"
+        ));
+    }
+    Ok(())
+}
 
-        let asset_hash = hex::encode(Sha256::digest(&asset_buffer));
-        if asset_hash != platform.asset_sha256 {
-            return Err(anyhow!("integrity mismatch TODO"));
+/// Check the contents of `name` against a Subresource Integrity hash, like
+/// `sha512-<base64>`. Any one matching hash in the list is enough.
+pub(crate) fn verify_integrity(name: &str, contents: &[u8], integrity: &str) -> Result<()> {
+    let mut supported = false;
+    for hash in integrity.split_whitespace() {
+        let (algorithm, expected) = match hash.split_once('-') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let actual = match algorithm {
+            "sha512" => Sha512::digest(contents).to_vec(),
+            "sha256" => Sha256::digest(contents).to_vec(),
+            _ => continue,
+        };
+        supported = true;
+        if base64::engine::general_purpose::STANDARD.encode(actual) == expected {
+            return Ok(());
         }
+    }
+    if supported {
+        Err(anyhow!(
+            "integrity mismatch for {name}, expected {integrity}"
+        ))
+    } else {
+        Err(anyhow!(
+            "no supported integrity hash for {name}: {integrity}"
+        ))
+    }
+}
 
-        let buf_reader = BufReader::new(std::io::Cursor::new(asset_buffer));
-        if url.ends_with(".tar.gz") {
-            let gz_decoder = GzDecoder::new(buf_reader);
-            let mut archive = Archive::new(gz_decoder);
+/// The Subresource Integrity hash of some contents, ex `sha256-<base64>`
+pub(crate) fn sha256_integrity(contents: &[u8]) -> String {
+    format!(
+        "sha256-{}",
+        base64::engine::general_purpose::STANDARD.encode(Sha256::digest(contents))
+    )
+}
 
-            for mut entry in archive
-                .entries()
-                .with_context(|| format!("Error finding entries in {asset_name}"))?
-                .filter_map(|entry| entry.ok())
-            {
-                let path = entry.path()?;
-                let path = path.as_os_str().to_string_lossy();
-                if self.should_install(&path) {
-                    entry
-                        .unpack_in(&project.sqlite_extensions_path())
-                        .with_context(|| {
-                            format!(
-                                "could not unpack tar.gz entry into {}",
-                                project.sqlite_extensions_path().display()
-                            )
-                        })?;
-                }
-            }
-        } else if url.ends_with(".zip") {
-            let mut zip_archive = ZipArchive::new(buf_reader)?;
+/// Fetch the spm.json at `url`, along with the integrity of its contents
+pub(crate) fn fetch_spm_json(url: &str) -> Result<(SpmPackageJson, String)> {
+    let contents = read_response(crate::http::http_get(url)?)
+        .with_context(|| format!("Could not fetch spm.json file at {url}"))?;
+    decode_spm_json(url, &contents)
+}
 
-            for i in 0..zip_archive.len() {
-                let mut file = zip_archive.by_index(i)?;
-                let mut contents = Vec::new();
-                file.read_to_end(&mut contents)?;
-                let path =
-                    std::path::Path::new(file.enclosed_name().ok_or_else(|| {
-                        anyhow!("Cannot determine encoded name in zipfile entry")
-                    })?)
-                    .file_name()
-                    .ok_or_else(|| anyhow!("Cannot determine file_name in zipfile entry"))?;
-                if self.should_install(&path.to_string_lossy()) {
-                    project.write_in_sqlite_extensions(path.into(), contents)?;
+/// Decode the contents of a spm.json fetched from `url`, along with their integrity
+pub(crate) fn decode_spm_json(url: &str, contents: &[u8]) -> Result<(SpmPackageJson, String)> {
+    let spm_json = serde_json::from_slice(contents)
+        .with_context(|| format!("Could not decode fetched spm.json into JSON, from {url}"))?;
+    Ok((spm_json, sha256_integrity(contents)))
+}
+
+/// Whether the file is a loadable library on any platform, ex `vec0.so` or `vec0.dll`
+pub(crate) fn is_loadable_file(name: &str) -> bool {
+    [".so", ".dylib", ".dll"]
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+}
+
+/// Unpack a .tar.gz, .tgz, .zip or .whl asset, or copy a bare loadable library, into the
+/// sqlite_extensions/ directory, skipping any files not listed in `artifacts`. With
+/// `loadables_only`, only the loadable libraries inside archives are installed, flattened.
+pub(crate) fn unpack_asset(
+    asset_name: &str,
+    asset_buffer: Vec<u8>,
+    artifacts: &Option<Vec<String>>,
+    loadables_only: bool,
+    project: &Project,
+) -> Result<()> {
+    let buf_reader = BufReader::new(std::io::Cursor::new(asset_buffer));
+    if asset_name.ends_with(".tar.gz") || asset_name.ends_with(".tgz") {
+        let gz_decoder = GzDecoder::new(buf_reader);
+        let mut archive = Archive::new(gz_decoder);
+
+        for mut entry in archive
+            .entries()
+            .with_context(|| format!("Error finding entries in {asset_name}"))?
+            .filter_map(|entry| entry.ok())
+        {
+            let path = entry.path()?.into_owned();
+            if loadables_only {
+                let file_name = match path.file_name() {
+                    Some(file_name) if is_loadable_file(&file_name.to_string_lossy()) => {
+                        file_name.to_owned()
+                    }
+                    _ => continue,
+                };
+                if should_install(artifacts, &file_name.to_string_lossy()) {
+                    let mut contents = Vec::new();
+                    entry.read_to_end(&mut contents)?;
+                    project.write_in_sqlite_extensions(file_name.into(), contents)?;
                 }
+                continue;
+            }
+            let path = path.as_os_str().to_string_lossy();
+            if should_install(artifacts, &path) {
+                entry
+                    .unpack_in(project.sqlite_extensions_path())
+                    .with_context(|| {
+                        format!(
+                            "could not unpack tar.gz entry into {}",
+                            project.sqlite_extensions_path().display()
+                        )
+                    })?;
             }
-        } else {
-            return Err(anyhow!(
-                "unknown asset format, expected .targz or .zip from {url}"
-            ));
         }
-
-        Ok(())
+    } else if asset_name.ends_with(".zip") || asset_name.ends_with(".whl") {
+        let mut zip_archive = ZipArchive::new(buf_reader)?;
+
+        for i in 0..zip_archive.len() {
+            let mut file = zip_archive.by_index(i)?;
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents)?;
+            let path = std::path::Path::new(
+                file.enclosed_name()
+                    .ok_or_else(|| anyhow!("Cannot determine encoded name in zipfile entry"))?,
+            )
+            .file_name()
+            .ok_or_else(|| anyhow!("Cannot determine file_name in zipfile entry"))?;
+            if loadables_only && !is_loadable_file(&path.to_string_lossy()) {
+                continue;
+            }
+            if should_install(artifacts, &path.to_string_lossy()) {
+                project.write_in_sqlite_extensions(path.into(), contents)?;
+            }
+        }
+    } else if is_loadable_file(asset_name) {
+        let path = std::path::Path::new(asset_name)
+            .file_name()
+            .ok_or_else(|| anyhow!("Cannot determine file_name of {asset_name}"))?;
+        if should_install(artifacts, &path.to_string_lossy()) {
+            project
+                .write_in_sqlite_extensions(path.into(), buf_reader.into_inner().into_inner())?;
+        }
+    } else {
+        return Err(anyhow!(
+            "unknown asset format, expected .targz, .zip, or a loadable library from {asset_name}"
+        ));
     }
+
+    Ok(())
 }
 
-fn github_parse_path(mut parts: Split<char>, prerelease: bool) -> Result<GithubReleasePackage> {
+fn github_parse_path(
+    host: &str,
+    mut parts: Split<char>,
+    prerelease: bool,
+    config: &SpmConfig,
+) -> Result<GithubReleasePackage> {
     let owner = parts
         .next()
         .ok_or_else(|| anyhow!("github owner name required"))?
@@ -523,8 +1534,12 @@ fn github_parse_path(mut parts: Split<char>, prerelease: bool) -> Result<GithubR
         .next()
         .ok_or_else(|| anyhow!("github repo name required"))?
         .to_owned();
+    let (base_url, api_url) = github_urls(host, config)?;
     if let Some((repo, version)) = repo.split_once('@') {
         Ok(GithubReleasePackage {
+            host: host.to_owned(),
+            base_url,
+            api_url,
             owner,
             repo: repo.to_owned(),
             version: Some(version.to_owned()),
@@ -532,6 +1547,9 @@ fn github_parse_path(mut parts: Split<char>, prerelease: bool) -> Result<GithubR
         })
     } else {
         Ok(GithubReleasePackage {
+            host: host.to_owned(),
+            base_url,
+            api_url,
             owner,
             repo: repo.to_owned(),
             version: None,
@@ -540,49 +1558,779 @@ fn github_parse_path(mut parts: Split<char>, prerelease: bool) -> Result<GithubR
     }
 }
 
-/// Given a package name, determine which resolver to use. Currently only supports Github Releases.
+/// The base web URL and API URL for a Github host, from the `hosts` config, then the
+/// `SPM_GITHUB_URL`/`SPM_GITHUB_API_URL` env vars for github.com, then the defaults.
+/// Github Enterprise Server serves its API at `/api/v3`.
+fn github_urls(host: &str, config: &SpmConfig) -> Result<(String, String)> {
+    let host_config = config.host(&Url::parse(&format!("https://{host}"))?);
+    let env_var = |name: &str| match host {
+        "github.com" => std::env::var(name).ok(),
+        _ => None,
+    };
+    let base_url = host_config
+        .and_then(|host| host.url.clone())
+        .or_else(|| env_var("SPM_GITHUB_URL"))
+        .unwrap_or_else(|| format!("https://{host}"));
+    let base_url = base_url.trim_end_matches('/').to_owned();
+    let api_url = host_config
+        .and_then(|host| host.api_url.clone())
+        .or_else(|| env_var("SPM_GITHUB_API_URL"))
+        .unwrap_or_else(|| match host {
+            "github.com" => "https://api.github.com".to_owned(),
+            _ => format!("{base_url}/api/v3"),
+        });
+    Ok((base_url, api_url.trim_end_matches('/').to_owned()))
+}
+
+/// Given a package name, determine which resolver to use.
 ///
 /// Examples:
 ///```
 /// "https://github.com/owner/repo" -> GithubReleaseResolver
 /// "github.com/owner/repo" -> GithubReleaseResolver
 /// "gh:owner/repo@v0.1.0" -> GithubReleaseResolver
+/// "https://github.mycorp.com/owner/repo" -> GithubReleaseResolver, with a `hosts` entry
+/// "https://gitlab.com/group/subgroup/project" -> GitlabReleasePackage
+/// "gitlab.com/group/project" -> GitlabReleasePackage
+/// "gl:group/project@v0.1.0" -> GitlabReleasePackage
+/// "https://codeberg.org/owner/repo" -> GiteaReleasePackage
+/// "https://example.com/sqlite-foo/v0.1.0/spm.json" -> HttpsPackage
+/// "sqlpkg:nalgeon/uuid" -> SqlpkgPackage
+/// "pypi:sqlite-vec@0.1.0" -> PypiPackage
+/// "npm:sqlite-vec@0.1.0" -> NpmPackage
+/// "oci://ghcr.io/asg017/sqlite-foo:v1.0.0" -> OciPackage
+/// "s3://my-bucket/sqlite-foo@v1.0.0" -> S3Package
+/// "hello@v0.1.0" -> IndexPackage
 /// ```
-fn determine_package_resolver(name: &str, prerelease: bool) -> Result<Box<dyn PackageResolver>> {
+pub(crate) fn determine_package_resolver(
+    name: &str,
+    prerelease: bool,
+    config: &SpmConfig,
+) -> Result<Box<dyn PackageResolver>> {
     if let Some(reference) = name.strip_prefix("gh:") {
         let parts = reference.split('/');
-        return Ok(Box::new(github_parse_path(parts, prerelease)?));
+        return Ok(Box::new(github_parse_path(
+            "github.com",
+            parts,
+            prerelease,
+            config,
+        )?));
     }
-    if let Ok(url) = Url::parse(name) {
-        return match url.host_str() {
-            Some("github.com") => {
-                let path_segments = url.path_segments().ok_or_else(|| anyhow!("wut"))?;
-                Ok(Box::new(github_parse_path(path_segments, prerelease)?))
-            }
-            Some(_) => Err(anyhow!("todo")),
-            None => Err(anyhow!("todo")),
+    if let Some(reference) = name.strip_prefix("s3://") {
+        return Ok(Box::new(S3Package::parse(reference, prerelease, config)?));
+    }
+    if let Some(reference) = name.strip_prefix("oci://") {
+        return Ok(Box::new(OciPackage::parse(reference, prerelease, config)?));
+    }
+    if let Some(reference) = name.strip_prefix("npm:") {
+        return Ok(Box::new(NpmPackage::parse(reference, prerelease, config)?));
+    }
+    if let Some(reference) = name.strip_prefix("pypi:") {
+        return Ok(Box::new(PypiPackage::parse(reference, prerelease, config)?));
+    }
+    if let Some(reference) = name.strip_prefix("sqlpkg:") {
+        return Ok(Box::new(SqlpkgPackage::parse(
+            reference, prerelease, config,
+        )?));
+    }
+    if let Some(reference) = name.strip_prefix("gl:") {
+        return Ok(Box::new(GitlabReleasePackage::parse(
+            "https://gitlab.com",
+            None,
+            reference,
+            prerelease,
+        )?));
+    }
+    if let Ok(url) = Url::parse(name) {
+        return match url.host_str() {
+            Some(_) => url_package_resolver(&url, prerelease, config),
+            None => Err(anyhow!("could not resolve package: {name}")),
         };
     }
     if let Some(reference) = name.strip_prefix("github.com/") {
         let parts = reference.split('/');
-        return Ok(Box::new(github_parse_path(parts, prerelease)?));
+        return Ok(Box::new(github_parse_path(
+            "github.com",
+            parts,
+            prerelease,
+            config,
+        )?));
+    }
+    // forge URLs without a scheme, ex "gitlab.com/group/project"
+    if let Ok(url) = Url::parse(&format!("https://{name}")) {
+        if matches!(url.host_str(), Some("gitlab.com" | "codeberg.org"))
+            || config.host(&url).is_some()
+        {
+            return url_package_resolver(&url, prerelease, config);
+        }
+    }
+    // short names, ex "hello", are looked up in the package index
+    if !name.is_empty() && !name.contains(['/', ':']) {
+        return Ok(Box::new(IndexPackage::parse(name, prerelease, config)?));
     }
     Err(anyhow!("could not resolve package: {name}"))
 }
+
+/// Why an spm.lock entry doesn't match its extension's definition in spm.toml, if it
+/// doesn't
+fn lock_mismatch(
+    definition: &SpmTomlExtensionDefinition,
+    lock: &SpmLockExtension,
+) -> Option<String> {
+    if let SpmTomlExtensionDefinition::Local { path, artifacts } = definition {
+        return match lock {
+            SpmLockExtension::Local(extension) if extension.path != *path => Some(format!(
+                "spm.lock has path {}, but spm.toml has {path}",
+                extension.path
+            )),
+            SpmLockExtension::Local(extension) if extension.artifacts != *artifacts => {
+                Some("the artifacts in spm.lock and spm.toml differ".to_owned())
+            }
+            SpmLockExtension::Local(_) => None,
+            _ => Some("spm.toml has a local path, but spm.lock doesn't".to_owned()),
+        };
+    }
+    if lock_satisfies(definition, lock) {
+        return None;
+    }
+    if lock.artifacts() != definition.artifacts().as_ref() {
+        return Some("the artifacts in spm.lock and spm.toml differ".to_owned());
+    }
+    Some(match (definition.version(), lock.version()) {
+        (Some(constraint), Some(version)) => {
+            format!("spm.lock has {version}, which doesn't match {constraint} in spm.toml")
+        }
+        _ => "spm.lock doesn't match the definition in spm.toml".to_owned(),
+    })
+}
+
+/// Whether an existing spm.lock entry still satisfies an extension's definition in
+/// spm.toml, so it doesn't need to be resolved again. Local extensions are always
+/// resolved again, since their files can change at any time.
+fn lock_satisfies(definition: &SpmTomlExtensionDefinition, lock: &SpmLockExtension) -> bool {
+    if lock.artifacts() != definition.artifacts().as_ref() {
+        return false;
+    }
+    if let SpmTomlExtensionDefinition::Definition {
+        registry: Some(registry),
+        ..
+    } = definition
+    {
+        if lock_registry(lock) != Some(registry.as_str()) {
+            return false;
+        }
+    }
+    if let Some(platforms) = definition.url_platforms() {
+        let extension = match lock {
+            SpmLockExtension::Https(extension) => extension,
+            _ => return false,
+        };
+        let loadable = &extension.spm_json.loadable;
+        return loadable.len() == platforms.len()
+            && platforms.iter().all(|platform| {
+                loadable.iter().any(|locked| {
+                    locked.asset_sha256.eq_ignore_ascii_case(&platform.sha256)
+                        && platform.os.as_ref().is_none_or(|os| *os == locked.os)
+                        && platform.cpu.as_ref().is_none_or(|cpu| *cpu == locked.cpu)
+                        && extension
+                            .resolved_assets
+                            .get(&locked.asset_name)
+                            .is_some_and(|url| {
+                                *url == platform.url || *url == crate::http::lock_url(&platform.url)
+                            })
+                })
+            });
+    }
+    let (constraint, version) = match (definition.version(), lock.version()) {
+        (Some(constraint), Some(version)) => (constraint, version),
+        _ => return false,
+    };
+    if constraint == version {
+        return true;
+    }
+    match version_req(constraint) {
+        Ok(Some(req)) => {
+            definition
+                .channel()
+                .is_none_or(|channel| in_channel(version, channel))
+                && highest_matching_tag([&version.to_owned()], &req, definition.prerelease())
+                    .is_some()
+        }
+        _ => false,
+    }
+}
+
+/// The registry that resolved an extension, if it was resolved through a package index
+fn lock_registry(lock: &SpmLockExtension) -> Option<&str> {
+    match lock {
+        SpmLockExtension::Https(extension) => extension.registry.as_deref(),
+        _ => None,
+    }
+}
+
+/// Refuse to silently switch an extension to another registry than the one recorded
+/// in spm.lock, unless spm.toml pins it to the new one.
+fn check_registry_unchanged(
+    name: &str,
+    definition: &SpmTomlExtensionDefinition,
+    previous: &SpmLockExtension,
+    lock: &SpmLockExtension,
+) -> Result<()> {
+    let (previous, current) = match (lock_registry(previous), lock_registry(lock)) {
+        (Some(previous), Some(current)) if previous != current => (previous, current),
+        _ => return Ok(()),
+    };
+    if matches!(definition, SpmTomlExtensionDefinition::Definition { registry: Some(registry), .. } if registry == current)
+    {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "{name} was resolved from the {previous} registry in spm.lock, but now resolves from {current}. Pin it with `registry = \"{current}\"` in spm.toml to switch."
+    ))
+}
+
+/// What a spm.lock entry pins the contents of its version with
+enum LockedContents<'a> {
+    /// Integrity of the fetched spm.json
+    Integrity(&'a str),
+    /// Digest of the OCI manifest, which lists the digest of every layer
+    ManifestDigest(&'a str),
+    /// The asset checksums of a spm.json that was inferred, or fetched by an older spm,
+    /// so has no integrity of its own
+    Assets(&'a SpmPackageJson),
+}
+
+/// The locked version of an extension and what pins its contents, if anything
+fn locked_contents(lock: &SpmLockExtension) -> Option<(&str, LockedContents<'_>)> {
+    let version = lock.version()?;
+    let (integrity, spm_json) = match lock {
+        SpmLockExtension::GithubRelease(extension) => (&extension.integrity, &extension.spm_json),
+        SpmLockExtension::Https(extension) => (&extension.integrity, &extension.spm_json),
+        SpmLockExtension::S3(extension) => (&extension.integrity, &extension.spm_json),
+        SpmLockExtension::Oci(extension) => {
+            return Some((
+                version,
+                LockedContents::ManifestDigest(&extension.manifest_digest),
+            ))
+        }
+        // local entries are re-read from disk every time
+        SpmLockExtension::Local(_) => return None,
+    };
+    match integrity.is_empty() {
+        true => Some((version, LockedContents::Assets(spm_json))),
+        false => Some((version, LockedContents::Integrity(integrity))),
+    }
+}
+
+/// Every (asset name, sha256) pair listed in a spm.json
+fn asset_checksums(spm_json: &SpmPackageJson) -> impl Iterator<Item = (&str, &str)> {
+    spm_json
+        .loadable
+        .iter()
+        .chain(spm_json.static_.iter().flatten())
+        .map(|platform| (platform.asset_name.as_str(), platform.asset_sha256.as_str()))
+}
+
+/// Refuse a release that changed since the same version was locked, ex when a
+/// maintainer replaced a release's spm.json or assets after publishing it. Assets that
+/// were added since are fine, only the locked ones have to be unchanged.
+fn check_integrity_unchanged(
+    name: &str,
+    previous: &SpmLockExtension,
+    lock: &SpmLockExtension,
+) -> Result<()> {
+    let (version, expected, actual) = match (locked_contents(previous), locked_contents(lock)) {
+        (Some((version, expected)), Some((current_version, actual)))
+            if version == current_version =>
+        {
+            (version, expected, actual)
+        }
+        _ => return Ok(()),
+    };
+    let change = match (expected, actual) {
+        (LockedContents::Integrity(expected), LockedContents::Integrity(actual))
+            if expected != actual =>
+        {
+            format!("spm.json of {name} {version} changed since it was locked: spm.lock has integrity {expected}, but it's now {actual}")
+        }
+        (LockedContents::ManifestDigest(expected), LockedContents::ManifestDigest(actual))
+            if expected != actual =>
+        {
+            format!("OCI manifest of {name} {version} changed since it was locked: spm.lock has digest {expected}, but it's now {actual}")
+        }
+        (LockedContents::Assets(expected), LockedContents::Assets(actual)) => {
+            let changed: Vec<&str> = asset_checksums(expected)
+                .filter(|(asset, sha256)| {
+                    !asset_checksums(actual).any(|(other, other_sha256)| {
+                        other == *asset && other_sha256.eq_ignore_ascii_case(sha256)
+                    })
+                })
+                .map(|(asset, _)| asset)
+                .collect();
+            if changed.is_empty() {
+                return Ok(());
+            }
+            format!(
+                "Assets of {name} {version} changed since they were locked: {}",
+                changed.join(", ")
+            )
+        }
+        _ => return Ok(()),
+    };
+    Err(anyhow!(
+        "{change}. If the new release is trusted, remove {name} from spm.lock and run `spm install`."
+    ))
+}
+
+/// Determine the resolver for a full URL, based on its host. Hosts that aren't a known
+/// forge, either by default or in the `hosts` config, are plain HTTPS servers.
+fn url_package_resolver(
+    url: &Url,
+    prerelease: bool,
+    config: &SpmConfig,
+) -> Result<Box<dyn PackageResolver>> {
+    let host = config.host(url);
+    let kind = host.and_then(|host| host.kind).or(match url.host_str() {
+        Some("gitlab.com") => Some(HostKind::Gitlab),
+        Some("github.com") => Some(HostKind::Github),
+        Some("codeberg.org") => Some(HostKind::Gitea),
+        _ => None,
+    });
+    match (url.host_str(), kind) {
+        (Some(_), Some(HostKind::Github)) => {
+            let host = host_key(url).ok_or_else(|| anyhow!("{url} has no host"))?;
+            let path_segments = url
+                .path_segments()
+                .ok_or_else(|| anyhow!("{url} is not a repository URL"))?;
+            Ok(Box::new(github_parse_path(
+                &host,
+                path_segments,
+                prerelease,
+                config,
+            )?))
+        }
+        (_, Some(HostKind::Gitlab)) => {
+            let base_url = host
+                .and_then(|host| host.url.clone())
+                .unwrap_or_else(|| url.origin().ascii_serialization());
+            Ok(Box::new(
+                GitlabReleasePackage::parse(
+                    &base_url,
+                    host.and_then(|host| host.api_url.as_deref()),
+                    url.path(),
+                    prerelease,
+                )?
+                .with_origin(&url.origin().ascii_serialization()),
+            ))
+        }
+        (_, Some(HostKind::Gitea)) => {
+            let base_url = host
+                .and_then(|host| host.url.clone())
+                .unwrap_or_else(|| url.origin().ascii_serialization());
+            Ok(Box::new(
+                GiteaReleasePackage::parse(
+                    &base_url,
+                    host.and_then(|host| host.api_url.as_deref()),
+                    url.path(),
+                    prerelease,
+                )?
+                .with_origin(&url.origin().ascii_serialization()),
+            ))
+        }
+        (None, Some(HostKind::Github)) | (_, None) => Ok(Box::new(HttpsPackage::parse(url)?)),
+    }
+}
+
+/// Whether a release tag looks like a pre-release, ex `v0.1.0-alpha.1`
+pub(crate) fn is_prerelease_tag(tag: &str) -> bool {
+    tag.trim_start_matches('v').contains('-')
+}
+
+/// The highest of the given tags that parses as a semver version (with an optional `v`
+/// prefix), skipping pre-releases unless `prerelease` is set
+pub(crate) fn latest_semver_tag<'a>(
+    tags: impl IntoIterator<Item = &'a String>,
+    prerelease: bool,
+) -> Option<String> {
+    tags.into_iter()
+        .filter(|tag| prerelease || !is_prerelease_tag(tag))
+        .filter_map(|tag| {
+            semver::Version::parse(tag.trim_start_matches('v'))
+                .ok()
+                .map(|version| (version, tag))
+        })
+        .max()
+        .map(|(_, tag)| tag.clone())
+}
+
+/// The semver range of a version from spm.toml, like `^0.1` or `>=0.2, <0.3`, or None
+/// if the version is an exact tag
+pub(crate) fn version_req(version: &str) -> Result<Option<semver::VersionReq>> {
+    if !(version.starts_with(['^', '~', '>', '<', '=', '*']) || version.contains(',')) {
+        return Ok(None);
+    }
+    semver::VersionReq::parse(version)
+        .map(Some)
+        .with_context(|| format!("invalid version range: {version}"))
+}
+
+/// The highest of the given tags (with an optional `v` prefix) that satisfies a semver
+/// range. Pre-releases only match when the range names a pre-release of the same
+/// version, or when `prerelease` is set.
+pub(crate) fn highest_matching_tag<'a>(
+    tags: impl IntoIterator<Item = &'a String>,
+    req: &semver::VersionReq,
+    prerelease: bool,
+) -> Option<String> {
+    tags.into_iter()
+        .filter_map(|tag| {
+            semver::Version::parse(tag.trim_start_matches('v'))
+                .ok()
+                .map(|version| (version, tag))
+        })
+        .filter(|(version, _)| {
+            if req.matches(version) {
+                return true;
+            }
+            if !prerelease || version.pre.is_empty() {
+                return false;
+            }
+            // semver only matches a pre-release against a comparator of the same version,
+            // so add one that every pre-release of this version satisfies
+            let mut req = req.clone();
+            req.comparators.push(semver::Comparator {
+                op: semver::Op::GreaterEq,
+                major: version.major,
+                minor: Some(version.minor),
+                patch: Some(version.patch),
+                pre: semver::Prerelease::new("0").unwrap(),
+            });
+            req.matches(version)
+        })
+        .max()
+        .map(|(_, tag)| tag.clone())
+}
+
+/// The name of an extension, with its old and new locked versions
+type VersionUpdate = (String, Option<String>, Option<String>);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// A published version of a package, as listed by `spm versions`
+pub struct PackageVersion {
+    pub version: String,
+    pub prerelease: bool,
+    /// Whether the version publishes a spm.json, or None if the source doesn't say
+    pub spm_json: Option<bool>,
+    /// Platforms that the version has assets for, as `{os}-{cpu}`. Empty if unknown.
+    pub platforms: Vec<String>,
+}
+
+impl PackageVersion {
+    /// A version that only its name is known of
+    pub fn new(version: String) -> PackageVersion {
+        PackageVersion {
+            prerelease: is_prerelease_tag(&version),
+            version,
+            spm_json: None,
+            platforms: vec![],
+        }
+    }
+
+    /// A release with the given asset names, with platforms inferred from the names
+    pub fn from_assets<'a>(
+        prerelease: bool,
+        assets: impl IntoIterator<Item = &'a str>,
+        version: String,
+    ) -> PackageVersion {
+        let mut spm_json = false;
+        let mut platforms = vec![];
+        for asset in assets {
+            spm_json |= asset == "spm.json";
+            if let Some((os, cpu)) = infer_platform(asset) {
+                platforms.push(format!("{os}-{cpu}"));
+            }
+        }
+        platforms.sort();
+        platforms.dedup();
+        PackageVersion {
+            version,
+            prerelease,
+            spm_json: Some(spm_json),
+            platforms,
+        }
+    }
+}
+
+/// Number of lines of release notes that `spm outdated --changelog` prints
+const CHANGELOG_EXCERPT_LINES: usize = 10;
+
+/// An extension in spm.lock with a newer version available
+struct OutdatedExtension {
+    name: String,
+    /// The locked version
+    current: String,
+    /// The newest version that the spm.toml constraint allows, if it could be resolved
+    wanted: Option<String>,
+    /// The latest published version
+    latest: String,
+    /// Release notes of the latest version, with `--changelog`
+    notes: Option<String>,
+}
+
+/// The constraint that `spm update --latest` writes for a new version. `^` and `~`
+/// ranges keep their operator, anything else becomes the exact version.
+fn updated_constraint(constraint: &str, version: &str) -> String {
+    match constraint.chars().next() {
+        Some(op @ ('^' | '~')) if !constraint.contains(',') => {
+            format!("{op}{}", version.trim_start_matches('v'))
+        }
+        _ => version.to_owned(),
+    }
+}
+
+/// Whether a tag is a stable release, or a pre-release in the given channel, which is
+/// the first identifier of the pre-release. Ex `v1.0.0-beta.2` is in the `beta` channel.
+pub(crate) fn in_channel(tag: &str, channel: &str) -> bool {
+    let version = tag
+        .trim_start_matches('v')
+        .split('+')
+        .next()
+        .unwrap_or_default();
+    match version.split_once('-') {
+        Some((_, pre)) => pre.split('.').next() == Some(channel),
+        None => true,
+    }
+}
+
+/// The published versions of an extension that the `channel` of its definition allows
+fn definition_versions(
+    resolver: &dyn PackageResolver,
+    definition: &SpmTomlExtensionDefinition,
+) -> Result<Vec<String>> {
+    Ok(resolver
+        .list_versions()?
+        .into_iter()
+        .map(|version| version.version)
+        .filter(|version| {
+            definition
+                .channel()
+                .is_none_or(|channel| in_channel(version, channel))
+        })
+        .collect())
+}
+
+/// The latest version of an extension that its definition allows. The resolver must
+/// be created with pre-releases allowed when the definition allows them.
+fn definition_latest_version(
+    resolver: &dyn PackageResolver,
+    definition: &SpmTomlExtensionDefinition,
+) -> Result<String> {
+    match definition.channel() {
+        Some(channel) => latest_semver_tag(&definition_versions(resolver, definition)?, true)
+            .ok_or_else(|| anyhow!("No {channel} versions found for {}", resolver.toml_name())),
+        None => resolver.latest_version(),
+    }
+}
+
+/// Replace a semver range in an extension definition with the highest published tag
+/// that satisfies it
+fn resolve_version_range(
+    resolver: &dyn PackageResolver,
+    definition: &SpmTomlExtensionDefinition,
+    prerelease: bool,
+) -> Result<SpmTomlExtensionDefinition> {
+    let range = match definition.version() {
+        Some(version) => version,
+        None => return Ok(definition.clone()),
+    };
+    let req = match version_req(range)? {
+        Some(req) => req,
+        None => return Ok(definition.clone()),
+    };
+    let versions = definition_versions(resolver, definition)?;
+    let prerelease = prerelease || definition.prerelease();
+    let version = highest_matching_tag(&versions, &req, prerelease)
+        .ok_or_else(|| anyhow!("No version of {} matches {range}", resolver.toml_name()))?;
+    Ok(definition.with_version(version))
+}
+
 pub trait PackageResolver {
     fn version_from_reference(&self) -> Result<String>;
     fn toml_name(&self) -> String;
     fn latest_version(&self) -> Result<String>;
+    /// Every published version of the package, across all pages of results
+    fn list_versions(&self) -> Result<Vec<PackageVersion>> {
+        Err(anyhow!(
+            "{} does not publish a list of versions",
+            self.toml_name()
+        ))
+    }
+    /// The release notes published with a version, if the source has any
+    fn release_notes(&self, _version: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
     fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension>;
 }
 
 struct GithubReleasePackage {
+    /// Host of the Github instance, ex `github.com` or `github.mycorp.com`
+    host: String,
+    /// Base web URL of the Github instance, ex `https://github.com`
+    base_url: String,
+    /// Base URL of the Github REST API, ex `https://api.github.com`
+    api_url: String,
     owner: String,
     repo: String,
     version: Option<String>,
     prerelease: bool,
 }
 
+impl GithubReleasePackage {
+    /// Fetch the spm.json published as a release asset and its integrity, or None if the
+    /// release doesn't have one
+    fn fetch_release_spm_json(
+        &self,
+        version: &str,
+        url: &str,
+    ) -> Result<Option<(SpmPackageJson, String)>> {
+        let request = if crate::http::has_token(&self.api_url) {
+            match self.fetch_release(version)?.asset("spm.json") {
+                Ok(asset) => asset.api_request()?,
+                Err(_) => return Ok(None),
+            }
+        } else {
+            crate::http::http_get(url)?
+        };
+        match request.call() {
+            Ok(response) => {
+                let mut contents = Vec::new();
+                response
+                    .into_reader()
+                    .read_to_end(&mut contents)
+                    .with_context(|| format!("Could not fetch spm.json file at {url}"))?;
+                Ok(Some(decode_spm_json(url, &contents)?))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("Could not fetch spm.json file at {url}")),
+        }
+    }
+
+    /// For releases without a spm.json asset, use the spm.json committed to the repo at
+    /// the release's tag. If there isn't one either, infer a spm.json from the release's
+    /// assets. Returns the URL the spm.json was resolved from, the spm.json, its integrity
+    /// (empty when inferred), and whether it was inferred.
+    fn fallback_spm_json(&self, version: &str) -> Result<(String, SpmPackageJson, String, bool)> {
+        let url = format!(
+            "{}/repos/{}/{}/contents/spm.json?ref={version}",
+            self.api_url, self.owner, self.repo
+        );
+        match crate::http::http_get(url.as_str())?
+            .set("Accept", "application/vnd.github.raw")
+            .call()
+        {
+            Ok(response) => {
+                let mut contents = Vec::new();
+                response
+                    .into_reader()
+                    .read_to_end(&mut contents)
+                    .with_context(|| format!("Could not fetch spm.json file at {url}"))?;
+                let (spm_json, integrity) = decode_spm_json(&url, &contents)?;
+                return Ok((url, spm_json, integrity, false));
+            }
+            Err(ureq::Error::Status(404, _)) => (),
+            Err(err) => return Err(err).with_context(|| format!("call to {url} failed")),
+        }
+
+        println!(
+            "{} {version} has no spm.json, inferring one from its release assets",
+            self.toml_name()
+        );
+        let release = self.fetch_release(version)?;
+        let checksums = match release
+            .assets
+            .iter()
+            .find(|asset| is_checksums_asset(&asset.name))
+        {
+            Some(asset) => {
+                let contents = read_response(asset.request()?)?;
+                parse_checksums(&String::from_utf8_lossy(&contents))
+            }
+            None => HashMap::new(),
+        };
+
+        let mut loadable: Vec<SpmPackageJsonPlatform> = vec![];
+        let mut static_: Vec<SpmPackageJsonPlatform> = vec![];
+        for asset in &release.assets {
+            if !(asset.name.ends_with(".tar.gz") || asset.name.ends_with(".zip")) {
+                continue;
+            }
+            let (os, cpu) = match infer_platform(&asset.name) {
+                Some(platform) => platform,
+                None => continue,
+            };
+            let platforms = if asset.name.to_lowercase().contains("static") {
+                &mut static_
+            } else {
+                &mut loadable
+            };
+            if platforms.iter().any(|p| p.os == os && p.cpu == cpu) {
+                continue;
+            }
+            let asset_sha256 = match checksums.get(&asset.name).cloned().or_else(|| {
+                asset
+                    .digest
+                    .as_ref()
+                    .and_then(|digest| digest.strip_prefix("sha256:"))
+                    .map(|digest| digest.to_owned())
+            }) {
+                Some(asset_sha256) => asset_sha256,
+                // no published checksum, so trust the asset as it is right now
+                None => hex::encode(Sha256::digest(read_response(asset.request()?)?)),
+            };
+            platforms.push(SpmPackageJsonPlatform {
+                os,
+                cpu,
+                asset_name: asset.name.clone(),
+                asset_sha256,
+                asset_md5: "".to_owned(),
+            });
+        }
+        if loadable.is_empty() {
+            return Err(anyhow!(
+                "{} {version} has no spm.json, and none of its release assets look like a loadable extension",
+                self.toml_name()
+            ));
+        }
+        let spm_json = SpmPackageJson {
+            version: 0,
+            description: "".to_owned(),
+            loadable,
+            static_: if static_.is_empty() {
+                None
+            } else {
+                Some(static_)
+            },
+        };
+        let url = format!(
+            "{}/repos/{}/{}/releases/tags/{version}",
+            self.api_url, self.owner, self.repo
+        );
+        Ok((url, spm_json, "".to_owned(), true))
+    }
+
+    fn fetch_release(&self, version: &str) -> Result<GithubRelease> {
+        let url = format!(
+            "{}/repos/{}/{}/releases/tags/{version}",
+            self.api_url, self.owner, self.repo
+        );
+        crate::http::http_get(url.as_str())?
+            .call()
+            .with_context(|| format!("call to {url} failed"))?
+            .into_json()
+            .with_context(|| format!("request did not return proper JSON: {url}"))
+    }
+}
+
 impl PackageResolver for GithubReleasePackage {
     fn version_from_reference(&self) -> Result<String> {
         match &self.version {
@@ -593,15 +2341,15 @@ impl PackageResolver for GithubReleasePackage {
         }
     }
     fn toml_name(&self) -> String {
-        format!("https://github.com/{}/{}", self.owner, self.repo)
+        format!("https://{}/{}/{}", self.host, self.owner, self.repo)
     }
     fn latest_version(&self) -> Result<String> {
         if self.prerelease {
             let url = format!(
-                "https://api.github.com/repos/{}/{}/releases?per_page=1",
-                self.owner, self.repo
+                "{}/repos/{}/{}/releases?per_page=1",
+                self.api_url, self.owner, self.repo
             );
-            let response: serde_json::Value = crate::http::http_get(url.as_str())
+            let response: serde_json::Value = crate::http::http_get(url.as_str())?
                 .call()
                 .with_context(|| format!("call to {url} failed"))?
                 .into_json()
@@ -617,10 +2365,10 @@ impl PackageResolver for GithubReleasePackage {
                 .to_owned());
         }
         let url = format!(
-            "https://api.github.com/repos/{}/{}/releases/latest",
-            self.owner, self.repo
+            "{}/repos/{}/{}/releases/latest",
+            self.api_url, self.owner, self.repo
         );
-        let response: serde_json::Value = crate::http::http_get(url.as_str())
+        let response: serde_json::Value = crate::http::http_get(url.as_str())?
             .call()
             .with_context(|| format!("call to {url} failed"))?
             .into_json()
@@ -633,27 +2381,41 @@ impl PackageResolver for GithubReleasePackage {
             .context("Expected 'tag_name' value to be a string")?
             .to_owned())
     }
-    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
-        let (version, artifacts) = match definition {
-            SpmTomlExtensionDefinition::Version(version) => (version.clone(), None),
-            SpmTomlExtensionDefinition::Definition { version, artifacts } => {
-                (version.clone(), artifacts.clone())
-            }
-        };
-        let resolved_url = format!("https://github.com/{}/{}", self.owner, self.repo);
-        let resolved_spm_json = format!(
-            "https://github.com/{}/{}/releases/download/{version}/spm.json",
-            self.owner, self.repo
+    fn list_versions(&self) -> Result<Vec<PackageVersion>> {
+        let url = format!(
+            "{}/repos/{}/{}/releases?per_page=100",
+            self.api_url, self.owner, self.repo
         );
+        let releases: Vec<GithubRelease> = crate::http::http_get_all_pages(&url)?;
+        Ok(releases
+            .into_iter()
+            .filter(|release| !release.draft)
+            .map(|release| {
+                PackageVersion::from_assets(
+                    release.prerelease || is_prerelease_tag(&release.tag_name),
+                    release.assets.iter().map(|asset| asset.name.as_str()),
+                    release.tag_name.clone(),
+                )
+            })
+            .collect())
+    }
+    fn release_notes(&self, version: &str) -> Result<Option<String>> {
+        Ok(self
+            .fetch_release(version)?
+            .body
+            .filter(|body| !body.trim().is_empty()))
+    }
+    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
+        let version = definition.required_version(&self.toml_name())?;
+        let artifacts = definition.artifacts();
+        let resolved_url = format!("{}/{}/{}", self.base_url, self.owner, self.repo);
+        let resolved_spm_json = format!("{resolved_url}/releases/download/{version}/spm.json");
 
-        let integrity = "".to_owned();
-
-        let url = resolved_spm_json.as_str();
-        let spm_json: SpmPackageJson = crate::http::http_get(url)
-            .call()
-            .with_context(|| format!("Could not fetch spm.json file at {url}"))?
-            .into_json()
-            .with_context(|| format!("Could not decode fetched spm.json into JSON, from {url}"))?;
+        let (resolved_spm_json, spm_json, integrity, inferred) =
+            match self.fetch_release_spm_json(&version, &resolved_spm_json)? {
+                Some((spm_json, integrity)) => (resolved_spm_json, spm_json, integrity, false),
+                None => self.fallback_spm_json(&version)?,
+            };
 
         Ok(SpmLockExtension::GithubRelease(GithubReleaseExtension {
             version,
@@ -662,6 +2424,7 @@ impl PackageResolver for GithubReleasePackage {
             resolved_spm_json,
             integrity,
             spm_json,
+            inferred,
         }))
     }
 }
@@ -756,7 +2519,671 @@ mod tests {
             SpmLockExtension::GithubRelease(gh) => {
                 assert_eq!(gh.version, "vX.X.X");
             }
+            _ => panic!("expected a Github Release lock entry"),
+        };
+    }
+
+    #[test]
+    fn test_github_enterprise() {
+        let spm_json = r#"{"version": 0, "description": "", "loadable": []}"#;
+        let server = crate::test_server::TestServer::start(vec![
+            (
+                "/api/v3/repos/owner/repo/releases/latest".to_owned(),
+                br#"{"tag_name": "v0.1.0"}"#.to_vec(),
+            ),
+            (
+                "/owner/repo/releases/download/v0.1.0/spm.json".to_owned(),
+                spm_json.as_bytes().to_vec(),
+            ),
+        ]);
+        let config: SpmConfig = toml::from_str(&format!(
+            r#"
+            [hosts."github.mycorp.com"]
+            kind = "github"
+            url = "{}"
+            "#,
+            server.url("")
+        ))
+        .unwrap();
+
+        let resolver =
+            determine_package_resolver("github.mycorp.com/owner/repo", false, &config).unwrap();
+        assert_eq!(resolver.toml_name(), "https://github.mycorp.com/owner/repo");
+        assert_eq!(resolver.latest_version().unwrap(), "v0.1.0");
+        match resolver
+            .generate_lock(&SpmTomlExtensionDefinition::Version("v0.1.0".to_owned()))
+            .unwrap()
+        {
+            SpmLockExtension::GithubRelease(gh) => {
+                assert_eq!(gh.resolved_url, server.url("/owner/repo"));
+            }
+            _ => panic!("expected a Github Release lock entry"),
+        }
+    }
+
+    #[test]
+    fn test_unpack_asset() {
+        let project = crate::test_server::temp_project("unpack-asset");
+        let extensions = project.sqlite_extensions_path();
+        let tgz = crate::test_server::tar_gz(&[
+            ("package/vec0.so", b"so"),
+            ("package/README.md", b"readme"),
+        ]);
+
+        // a .tgz from any other source is unpacked as is
+        unpack_asset("foo.tgz", tgz.clone(), &None, false, &project).unwrap();
+        assert!(extensions.join("package/vec0.so").exists());
+        assert!(extensions.join("package/README.md").exists());
+
+        unpack_asset("foo.tgz", tgz, &None, true, &project).unwrap();
+        assert!(extensions.join("vec0.so").exists());
+        assert!(!extensions.join("README.md").exists());
+
+        // and so is a .whl
+        let whl = crate::test_server::zip(&[("foo/vec1.so", b"so"), ("foo/__init__.py", b"")]);
+        unpack_asset("foo.whl", whl.clone(), &None, false, &project).unwrap();
+        assert!(extensions.join("__init__.py").exists());
+        std::fs::remove_file(extensions.join("__init__.py")).unwrap();
+        unpack_asset("foo.whl", whl, &None, true, &project).unwrap();
+        assert!(extensions.join("vec1.so").exists());
+        assert!(!extensions.join("__init__.py").exists());
+    }
+
+    #[test]
+    fn test_infer_platform() {
+        let platform = |os: &str, cpu: &str| Some((os.to_owned(), cpu.to_owned()));
+        assert_eq!(
+            infer_platform("sqlite-foo-v0.1.0-loadable-linux-x86_64.tar.gz"),
+            platform("linux", "x86_64")
+        );
+        assert_eq!(
+            infer_platform("foo_Darwin_arm64.zip"),
+            platform("macos", "aarch64")
+        );
+        assert_eq!(
+            infer_platform("foo-x86_64-pc-windows-msvc.zip"),
+            platform("windows", "x86_64")
+        );
+        assert_eq!(infer_platform("foo-linux-riscv64.tar.gz"), None);
+        assert_eq!(infer_platform("checksums.txt"), None);
+    }
+
+    #[test]
+    fn test_version_ranges() {
+        assert!(version_req("v0.1.0").unwrap().is_none());
+        assert!(version_req("latest").unwrap().is_none());
+        assert!(version_req("^0.1 ^0.2").is_err());
+
+        let tags: Vec<String> = [
+            "v0.1.0",
+            "v0.1.5",
+            "v0.2.0-alpha.1",
+            "v0.2.0",
+            "v0.2.1",
+            "nightly",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        let highest = |range: &str, prerelease: bool| {
+            highest_matching_tag(&tags, &version_req(range).unwrap().unwrap(), prerelease)
+        };
+        assert_eq!(highest("^0.1", false).unwrap(), "v0.1.5");
+        assert_eq!(highest("~0.1.2", false).unwrap(), "v0.1.5");
+        assert_eq!(highest(">=0.2, <0.3", false).unwrap(), "v0.2.1");
+        assert_eq!(highest(">0.1.5, <0.2.0", false), None);
+        assert_eq!(highest(">0.1.5, <0.2.0", true).unwrap(), "v0.2.0-alpha.1");
+        assert_eq!(
+            highest(">=0.2.0-alpha, <0.2.0", false).unwrap(),
+            "v0.2.0-alpha.1"
+        );
+        assert_eq!(highest("*", false).unwrap(), "v0.2.1");
+    }
+
+    #[test]
+    fn test_github_inferred_spm_json() {
+        let linux = crate::test_server::tar_gz(&[("foo0.so", b"linux")]);
+        let release = serde_json::json!({
+          "tag_name": "v0.1.0",
+          "assets": [
+            {
+              "name": "foo-linux-x86_64.tar.gz",
+              "url": "$SERVER/api/v3/assets/1",
+              "browser_download_url": "$SERVER/owner/repo/releases/download/v0.1.0/foo-linux-x86_64.tar.gz"
+            },
+            {
+              "name": "foo-macos-aarch64.tar.gz",
+              "url": "$SERVER/api/v3/assets/2",
+              "browser_download_url": "$SERVER/owner/repo/releases/download/v0.1.0/foo-macos-aarch64.tar.gz"
+            },
+            {
+              "name": "checksums.txt",
+              "url": "$SERVER/api/v3/assets/3",
+              "browser_download_url": "$SERVER/owner/repo/releases/download/v0.1.0/checksums.txt"
+            }
+          ]
+        });
+        let server = crate::test_server::TestServer::start(vec![
+            (
+                "/api/v3/repos/owner/repo/releases/tags/v0.1.0".to_owned(),
+                release.to_string().into_bytes(),
+            ),
+            (
+                "/owner/repo/releases/download/v0.1.0/checksums.txt".to_owned(),
+                format!("{}  foo-macos-aarch64.tar.gz\n", "a".repeat(64)).into_bytes(),
+            ),
+            (
+                "/owner/repo/releases/download/v0.1.0/foo-linux-x86_64.tar.gz".to_owned(),
+                linux.clone(),
+            ),
+        ]);
+        let config: SpmConfig = toml::from_str(&format!(
+            "[hosts.\"github.mycorp.com\"]\nkind = \"github\"\nurl = \"{}\"",
+            server.url("")
+        ))
+        .unwrap();
+
+        let resolver =
+            determine_package_resolver("github.mycorp.com/owner/repo", false, &config).unwrap();
+        let gh = match resolver
+            .generate_lock(&SpmTomlExtensionDefinition::Version("v0.1.0".to_owned()))
+            .unwrap()
+        {
+            SpmLockExtension::GithubRelease(gh) => gh,
+            _ => panic!("expected a Github Release lock entry"),
+        };
+        assert!(gh.inferred);
+        let loadable = &gh.spm_json.loadable;
+        assert_eq!(loadable.len(), 2);
+        assert_eq!(loadable[0].os, "linux");
+        assert_eq!(
+            loadable[0].asset_sha256,
+            hex::encode(Sha256::digest(&linux))
+        );
+        assert_eq!(loadable[1].cpu, "aarch64");
+        assert_eq!(loadable[1].asset_sha256, "a".repeat(64));
+    }
+
+    /// Write a package into a local package index at `index`, with every version
+    /// pointing at an empty spm.json
+    fn publish_to_index(index: &std::path::Path, name: &str, versions: &[&str]) {
+        std::fs::create_dir_all(index).unwrap();
+        std::fs::write(
+            index.join("spm.json"),
+            r#"{"version": 0, "description": "", "loadable": []}"#,
+        )
+        .unwrap();
+        let versions: Vec<_> = versions
+            .iter()
+            .map(|version| serde_json::json!({"version": version, "spm_json": "spm.json"}))
+            .collect();
+        std::fs::write(
+            index.join(format!("{name}.json")),
+            serde_json::json!({"name": name, "versions": versions}).to_string(),
+        )
+        .unwrap();
+    }
+
+    /// Write a spm.toml with the given `[extensions]`, resolved from a local package
+    /// index in the project's index/ directory
+    fn write_spm_toml(project: &Project, extensions: &str) {
+        project
+            .write_spm_toml_contents(format!(
+                "[registries.local]\nurl = '{}'\n\n[extensions]\n{extensions}",
+                project.resolve_path("index").display()
+            ))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_project_tokens() {
+        let project = crate::test_server::temp_project("tokens");
+        project
+            .write_spm_toml_contents(
+                r#"
+                [hosts."git.mycorp.com"]
+                api_url = "https://collect.example.com"
+
+                [hosts."gitlab.example.com"]
+                kind = "gitlab"
+                api_url = "https://gitlab.example.com/api/v4"
+                token = "def"
+
+                [extensions]
+                "#,
+            )
+            .unwrap();
+        let user_config: SpmConfig =
+            toml::from_str("[hosts.\"git.mycorp.com\"]\nkind = 'gitea'\ntoken = 'abc'").unwrap();
+        let (config, tokens) = project.project_config(user_config).unwrap();
+        assert_eq!(
+            config.hosts.as_ref().unwrap()["git.mycorp.com"].api_url,
+            Some("https://collect.example.com".to_owned())
+        );
+        assert!(tokens.contains(&("git.mycorp.com".to_owned(), "abc".to_owned())));
+        for host in ["collect.example.com", "gitlab.example.com"] {
+            assert!(!tokens.iter().any(|(token_host, _)| token_host == host));
+        }
+    }
+
+    #[test]
+    fn test_relative_registry() {
+        let project = crate::test_server::temp_project("relative-registry");
+        publish_to_index(&project.resolve_path("index"), "a", &["v0.1.0"]);
+        project
+            .write_spm_toml_contents("[registries.local]\nurl = 'index'\n\n[extensions]\n")
+            .unwrap();
+        // tests run from the crate root, not from the project
+        assert_ne!(std::env::current_dir().unwrap(), project.resolve_path(""));
+        let (config, _) = project.project_config(SpmConfig::default()).unwrap();
+        let package = IndexPackage::parse("a", false, &config).unwrap();
+        assert_eq!(package.latest_version().unwrap(), "v0.1.0");
+    }
+
+    #[test]
+    fn test_update() {
+        let project = crate::test_server::temp_project("update");
+        let index = project.resolve_path("index");
+        let publish = |name: &str, versions: &[&str]| publish_to_index(&index, name, versions);
+        publish("a", &["v0.1.0"]);
+        publish("b", &["v0.1.0"]);
+        write_spm_toml(&project, "a = \"^0.1\"\nb = { version = \"v0.1.0\" }\n");
+        project.generate_lockfile().unwrap();
+
+        publish("a", &["v0.1.0", "v0.1.1", "v0.2.0"]);
+        publish("b", &["v0.1.0", "v0.2.0"]);
+        let version = |version: &str| Some(version.to_owned());
+        assert_eq!(
+            project.update_lockfile(&["a".to_owned()], false).unwrap(),
+            vec![("a".to_owned(), version("v0.1.0"), version("v0.1.1"))]
+        );
+        assert_eq!(
+            project.update_lockfile(&[], false).unwrap(),
+            vec![
+                ("a".to_owned(), version("v0.1.1"), version("v0.1.1")),
+                ("b".to_owned(), version("v0.1.0"), version("v0.1.0"))
+            ]
+        );
+        assert_eq!(
+            project.update_lockfile(&["b".to_owned()], true).unwrap(),
+            vec![("b".to_owned(), version("v0.1.0"), version("v0.2.0"))]
+        );
+        let spm_toml = project.read_spm_toml_contents().unwrap();
+        assert!(spm_toml.contains("a = \"^0.1\""));
+        assert!(spm_toml.contains("b = { version = \"v0.2.0\" }"));
+        let lock = project.read_spm_lock().unwrap();
+        assert_eq!(lock.extensions["a"].version(), Some("v0.1.1"));
+
+        project.update_lockfile(&[], true).unwrap();
+        assert!(project
+            .read_spm_toml_contents()
+            .unwrap()
+            .contains("a = \"^0.2.0\""));
+        assert!(project.update_lockfile(&["c".to_owned()], false).is_err());
+    }
+
+    #[test]
+    fn test_outdated() {
+        let project = crate::test_server::temp_project("outdated");
+        let index = project.resolve_path("index");
+        publish_to_index(&index, "a", &["v0.1.0"]);
+        publish_to_index(&index, "b", &["v0.1.0"]);
+        publish_to_index(&index, "c", &["v1.0.0"]);
+        write_spm_toml(&project, "a = \"^0.1\"\nb = \"v0.1.0\"\nc = \"v1.0.0\"\n");
+        project.generate_lockfile().unwrap();
+        assert!(project.outdated(false, false).unwrap().is_empty());
+        assert!(!project.command_outdated(false, false).unwrap());
+
+        publish_to_index(
+            &index,
+            "a",
+            &[
+                "v0.1.0",
+                "v0.1.1",
+                "v0.1.2-alpha.1",
+                "v0.2.0",
+                "v0.3.0-alpha.1",
+            ],
+        );
+        publish_to_index(&index, "b", &["v0.1.0", "v0.2.0"]);
+        assert!(project.command_outdated(false, false).unwrap());
+        let outdated = project.outdated(false, true).unwrap();
+        let versions: Vec<_> = outdated
+            .iter()
+            .map(|extension| {
+                (
+                    extension.name.as_str(),
+                    extension.current.as_str(),
+                    extension.wanted.as_deref().unwrap_or("-"),
+                    extension.latest.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            versions,
+            vec![
+                ("a", "v0.1.0", "v0.1.1", "v0.2.0"),
+                ("b", "v0.1.0", "v0.1.0", "v0.2.0")
+            ]
+        );
+        assert!(outdated[0].notes.is_none());
+        let outdated = project.outdated(true, false).unwrap();
+        assert_eq!(outdated[0].latest, "v0.3.0-alpha.1");
+        assert_eq!(outdated[0].wanted.as_deref(), Some("v0.1.1"));
+
+        // a constraint that nothing satisfies is reported, not an error
+        write_spm_toml(&project, "a = \"^0.1\"\nb = \"v0.1.0\"\nc = \"^2\"\n");
+        let outdated = project.outdated(false, false).unwrap();
+        let c = outdated
+            .iter()
+            .find(|extension| extension.name == "c")
+            .unwrap();
+        assert_eq!((c.wanted.as_deref(), c.latest.as_str()), (None, "v1.0.0"));
+    }
+
+    #[test]
+    fn test_versions() {
+        let project = crate::test_server::temp_project("versions");
+        let index = project.resolve_path("index");
+        publish_to_index(
+            &index,
+            "a",
+            &["v0.1.0", "v0.10.0", "v0.2.0-alpha.1", "v0.2.0"],
+        );
+        write_spm_toml(&project, "a = \"v0.1.0\"\n");
+        let versions = project.versions("a").unwrap();
+        let names: Vec<_> = versions
+            .iter()
+            .map(|version| version.version.as_str())
+            .collect();
+        assert_eq!(names, vec!["v0.10.0", "v0.2.0", "v0.2.0-alpha.1", "v0.1.0"]);
+        assert!(versions[2].prerelease);
+        assert!(!versions[1].prerelease);
+        assert_eq!(versions[0].spm_json, Some(true));
+        assert!(project.versions("b").is_err());
+
+        let release = PackageVersion::from_assets(
+            false,
+            [
+                "spm.json",
+                "sqlite-a-v0.1.0-loadable-linux-x86_64.tar.gz",
+                "sqlite-a-v0.1.0-loadable-macos-aarch64.tar.gz",
+                "sqlite-a-v0.1.0-static-linux-x86_64.tar.gz",
+            ],
+            "v0.1.0".to_owned(),
+        );
+        assert_eq!(release.spm_json, Some(true));
+        assert_eq!(release.platforms, vec!["linux-x86_64", "macos-aarch64"]);
+    }
+
+    #[test]
+    fn test_prerelease_policy() {
+        let project = crate::test_server::temp_project("prerelease-policy");
+        let index = project.resolve_path("index");
+        let versions = ["v0.1.0", "v0.2.0-alpha.1", "v0.2.0-beta.1"];
+        for name in ["a", "b", "c", "d", "e"] {
+            publish_to_index(&index, name, &versions);
+        }
+        write_spm_toml(
+            &project,
+            r#"
+            a = { version = ">=0.1", prerelease = true }
+            b = { version = ">=0.1", channel = "alpha" }
+            c = ">=0.1"
+            d = { version = "v0.1.0", channel = "alpha" }
+            e = { version = ">=0.1", channel = "a" }
+            "#,
+        );
+        project.generate_lockfile().unwrap();
+        let lock = project.read_spm_lock().unwrap();
+        assert_eq!(lock.extensions["a"].version(), Some("v0.2.0-beta.1"));
+        assert_eq!(lock.extensions["b"].version(), Some("v0.2.0-alpha.1"));
+        assert_eq!(lock.extensions["c"].version(), Some("v0.1.0"));
+        assert_eq!(lock.extensions["e"].version(), Some("v0.1.0"));
+
+        let outdated = project.outdated(false, false).unwrap();
+        assert_eq!(outdated.len(), 1);
+        assert_eq!(outdated[0].name, "d");
+        assert_eq!(outdated[0].latest, "v0.2.0-alpha.1");
+
+        assert!(in_channel("v1.0.0", "beta"));
+        assert!(in_channel("v1.0.0-beta.2", "beta"));
+        assert!(!in_channel("v1.0.0-alpha.2", "beta"));
+        assert!(in_channel("v1.0.0-rc+build-1", "rc"));
+        assert!(!in_channel("v1.0.0-alpha.1", "a"));
+        assert!(!in_channel("v1.0.0-rcx.1", "rc"));
+    }
+
+    #[test]
+    fn test_incremental_lockfile() {
+        let project = crate::test_server::temp_project("incremental-lockfile");
+        let index = project.resolve_path("index");
+        for name in ["a", "b", "c"] {
+            publish_to_index(&index, name, &["v0.1.0"]);
+        }
+        write_spm_toml(&project, "a = \"^0.1\"\nb = \"v0.1.0\"\nc = \"v0.1.0\"\n");
+        project.generate_lockfile().unwrap();
+        let contents = project.read_spm_lock_contents().unwrap();
+
+        // unchanged entries aren't resolved again, even when newer versions match
+        publish_to_index(&index, "a", &["v0.1.0", "v0.1.1"]);
+        std::fs::remove_file(index.join("b.json")).unwrap();
+        project.generate_lockfile().unwrap();
+        assert_eq!(project.read_spm_lock_contents().unwrap(), contents);
+
+        // changed artifacts are resolved again, and removed extensions are dropped
+        write_spm_toml(
+            &project,
+            "a = { version = \"^0.1\", artifacts = [\"a0\"] }\nb = \"v0.1.0\"\n",
+        );
+        project.generate_lockfile().unwrap();
+        let lock = project.read_spm_lock().unwrap();
+        assert_eq!(lock.extensions.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(lock.extensions["a"].version(), Some("v0.1.1"));
+        assert_eq!(lock.extensions["b"].version(), Some("v0.1.0"));
+
+        // changed versions are resolved again
+        write_spm_toml(
+            &project,
+            "a = { version = \"^0.1\", artifacts = [\"a0\"] }\nb = \"v0.2.0\"\n",
+        );
+        assert!(project.generate_lockfile().is_err());
+        publish_to_index(&index, "b", &["v0.1.0", "v0.2.0"]);
+        project.generate_lockfile().unwrap();
+        assert_eq!(
+            project.read_spm_lock().unwrap().extensions["b"].version(),
+            Some("v0.2.0")
+        );
+    }
+
+    #[test]
+    fn test_url_lock_satisfies() {
+        let spm_toml: SpmToml = toml::from_str(
+            r#"
+            [extensions]
+            vec = { url = "https://example.com/vec0.so", sha256 = "ABC123", os = "linux", cpu = "x86_64" }
+            "#,
+        )
+        .unwrap();
+        let definition = &spm_toml.extensions["vec"];
+        let lock = UrlPackage::new("vec", definition.url_platforms().unwrap())
+            .generate_lock(definition)
+            .unwrap();
+        // checksums are locked in lowercase
+        assert!(lock_satisfies(definition, &lock));
+        assert_eq!(lock_mismatch(definition, &lock), None);
+    }
+
+    #[test]
+    fn test_check_lockfile() {
+        let project = crate::test_server::temp_project("check-lockfile");
+        let index = project.resolve_path("index");
+        for name in ["a", "b"] {
+            publish_to_index(&index, name, &["v0.1.0", "v0.2.0"]);
+        }
+        write_spm_toml(&project, "a = \"^0.1\"\nb = \"v0.1.0\"\n");
+        assert!(project.check_lockfile().is_err());
+        project.generate_lockfile().unwrap();
+        assert!(!project.read_spm_lock().unwrap().fingerprint.is_empty());
+        project.check_lockfile().unwrap();
+
+        // formatting doesn't change the fingerprint
+        write_spm_toml(&project, "b = \"v0.1.0\"   # pinned\na = \"^0.1\"\n");
+        project.check_lockfile().unwrap();
+
+        write_spm_toml(&project, "b = \"v0.2.0\"\nc = \"v0.1.0\"\n");
+        let error = project.check_lockfile().unwrap_err().to_string();
+        assert!(error.contains("b: spm.lock has v0.1.0, which doesn't match v0.2.0 in spm.toml"));
+        assert!(error.contains("c: in spm.toml, but not in spm.lock"));
+        assert!(error.contains("a: in spm.lock, but not in spm.toml"));
+
+        // changes that the locked versions still satisfy fail through the fingerprint
+        write_spm_toml(
+            &project,
+            "a = { version = \"^0.1\", prerelease = true }\nb = \"v0.1.0\"\n",
+        );
+        assert!(project
+            .check_lockfile()
+            .unwrap_err()
+            .to_string()
+            .contains("changed since spm.lock was generated"));
+    }
+
+    #[test]
+    fn test_integrity() {
+        let project = crate::test_server::temp_project("integrity");
+        let index = project.resolve_path("index");
+        publish_to_index(&index, "a", &["v0.1.0"]);
+        write_spm_toml(&project, "a = \"^0.1\"\n");
+        project.generate_lockfile().unwrap();
+        let spm_json = std::fs::read(index.join("spm.json")).unwrap();
+        let integrity = match &project.read_spm_lock().unwrap().extensions["a"] {
+            SpmLockExtension::Https(extension) => extension.integrity.clone(),
+            lock => panic!("unexpected lock {lock:?}"),
+        };
+        assert!(integrity.starts_with("sha256-"));
+        assert_eq!(integrity, sha256_integrity(&spm_json));
+
+        // re-locking the same version verifies spm.json against spm.lock
+        project.update_lockfile(&[], false).unwrap();
+        std::fs::write(
+            index.join("spm.json"),
+            r#"{"version": 0, "description": "replaced", "loadable": []}"#,
+        )
+        .unwrap();
+        let contents = project.read_spm_lock_contents().unwrap();
+        assert!(project
+            .update_lockfile(&[], false)
+            .unwrap_err()
+            .to_string()
+            .contains("spm.json of a v0.1.0 changed since it was locked"));
+        assert_eq!(project.read_spm_lock_contents().unwrap(), contents);
+
+        // a new version has nothing to be verified against
+        publish_to_index(&index, "a", &["v0.1.0", "v0.1.1"]);
+        std::fs::write(
+            index.join("spm.json"),
+            r#"{"version": 0, "description": "new", "loadable": []}"#,
+        )
+        .unwrap();
+        project.update_lockfile(&[], false).unwrap();
+    }
+
+    #[test]
+    fn test_locked_contents() {
+        let spm_json = |sha256: &str| SpmPackageJson {
+            loadable: vec![SpmPackageJsonPlatform {
+                asset_name: "foo-linux-x86_64.tar.gz".to_owned(),
+                asset_sha256: sha256.to_owned(),
+                ..Default::default()
+            }],
+            ..Default::default()
         };
+        let inferred = |version: &str, sha256: &str| {
+            SpmLockExtension::Https(HttpsExtension {
+                version: version.to_owned(),
+                spm_json: spm_json(sha256),
+                inferred: true,
+                ..Default::default()
+            })
+        };
+        let previous = inferred("v0.1.0", "aaa");
+        assert!(check_integrity_unchanged("a", &previous, &inferred("v0.1.0", "AAA")).is_ok());
+        assert!(check_integrity_unchanged("a", &previous, &inferred("v0.2.0", "bbb")).is_ok());
+        assert!(
+            check_integrity_unchanged("a", &previous, &inferred("v0.1.0", "bbb"))
+                .unwrap_err()
+                .to_string()
+                .contains(
+                    "Assets of a v0.1.0 changed since they were locked: foo-linux-x86_64.tar.gz"
+                )
+        );
+        // new assets alone aren't a change
+        let mut added = inferred("v0.1.0", "aaa");
+        if let SpmLockExtension::Https(extension) = &mut added {
+            extension.spm_json.loadable.push(SpmPackageJsonPlatform {
+                asset_name: "foo-macos-aarch64.tar.gz".to_owned(),
+                ..Default::default()
+            });
+        }
+        assert!(check_integrity_unchanged("a", &previous, &added).is_ok());
+
+        let oci = |version: &str, manifest_digest: &str| {
+            SpmLockExtension::Oci(OciExtension {
+                version: version.to_owned(),
+                manifest_digest: manifest_digest.to_owned(),
+                spm_json: spm_json("aaa"),
+                ..Default::default()
+            })
+        };
+        let previous = oci("v0.1.0", "sha256:aaa");
+        assert!(check_integrity_unchanged("b", &previous, &oci("v0.1.0", "sha256:aaa")).is_ok());
+        assert!(check_integrity_unchanged("b", &previous, &oci("v0.2.0", "sha256:bbb")).is_ok());
+        assert!(
+            check_integrity_unchanged("b", &previous, &oci("v0.1.0", "sha256:bbb"))
+                .unwrap_err()
+                .to_string()
+                .contains("OCI manifest of b v0.1.0 changed since it was locked")
+        );
+    }
+
+    #[test]
+    fn test_locked() {
+        let mut project = crate::test_server::temp_project("locked");
+        let index = project.resolve_path("index");
+        for name in ["a", "b"] {
+            publish_to_index(&index, name, &["v0.1.0", "v0.2.0"]);
+        }
+        write_spm_toml(&project, "a = \"^0.1\"\nb = \"v0.1.0\"\n");
+        project.generate_lockfile().unwrap();
+        let contents = project.read_spm_lock_contents().unwrap();
+
+        project.locked = true;
+        project.generate_lockfile().unwrap();
+        assert!(project
+            .update_lockfile(&[], false)
+            .unwrap_err()
+            .to_string()
+            .contains("spm update needs to update spm.lock"));
+
+        write_spm_toml(&project, "a = \"^0.1\"\nb = \"v0.2.0\"\n");
+        assert!(project
+            .generate_lockfile()
+            .unwrap_err()
+            .to_string()
+            .contains("Locking b needs to update spm.lock"));
+
+        write_spm_toml(&project, "a = \"^0.1\"\n");
+        assert!(project
+            .generate_lockfile()
+            .unwrap_err()
+            .to_string()
+            .contains("Removing b needs to update spm.lock"));
+
+        // edits that the locked entries still satisfy don't need a new spm.lock
+        write_spm_toml(&project, "a = \">=0.1\"\nb = \"v0.1.0\"\n");
+        project.generate_lockfile().unwrap();
+        assert_eq!(project.read_spm_lock_contents().unwrap(), contents);
     }
 
     #[test]
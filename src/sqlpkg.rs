@@ -0,0 +1,328 @@
+use crate::config::SpmConfig;
+use crate::https::HttpsExtension;
+use crate::spm::{
+    determine_package_resolver, read_location, PackageResolver, PackageVersion, SpmLockExtension,
+    SpmPackageJson, SpmPackageJsonPlatform, SpmTomlExtensionDefinition,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+
+/// Default location of the sqlpkg registry, a checkout of github.com/nalgeon/sqlpkg
+const DEFAULT_REGISTRY: &str = "https://github.com/nalgeon/sqlpkg/raw/main";
+
+/// sqlpkg (os, arch) names, and the matching spm (os, cpu) names
+const PLATFORMS: [(&str, &str, &str, &str); 5] = [
+    ("darwin", "amd64", "macos", "x86_64"),
+    ("darwin", "arm64", "macos", "aarch64"),
+    ("linux", "amd64", "linux", "x86_64"),
+    ("linux", "arm64", "linux", "aarch64"),
+    ("windows", "amd64", "windows", "x86_64"),
+];
+
+#[derive(Debug, Deserialize)]
+/// A sqlpkg package spec, ex `pkg/nalgeon/uuid.json` in the sqlpkg registry
+struct SqlpkgSpec {
+    owner: String,
+    name: String,
+    /// Version of the package, or "latest" for the latest release of `repository`
+    #[serde(default)]
+    version: String,
+    repository: Option<String>,
+    #[serde(default)]
+    description: String,
+    assets: SqlpkgAssets,
+}
+
+#[derive(Debug, Deserialize)]
+struct SqlpkgAssets {
+    /// Base URL of the assets, with placeholders like `{repository}` and `{version}`
+    path: String,
+    /// Asset file name with `{os}` and `{arch}` placeholders, used when `files` is empty
+    pattern: Option<String>,
+    /// Asset file names keyed by `{os}-{arch}`, ex `linux-amd64`
+    #[serde(default)]
+    files: HashMap<String, String>,
+    /// Checksums keyed by asset file name, ex `sha256-abc...`
+    #[serde(default)]
+    checksums: HashMap<String, String>,
+}
+
+/// Read the contents of an asset, or None if it doesn't exist
+fn read_asset(location: &str) -> Result<Option<Vec<u8>>> {
+    if !(location.starts_with("http://") || location.starts_with("https://")) {
+        if !std::path::Path::new(location).exists() {
+            return Ok(None);
+        }
+        return read_location(location).map(Some);
+    }
+    let response = match crate::http::http_get(location)?.call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Error making request to {location}")),
+    };
+    let mut contents = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut contents)
+        .with_context(|| format!("Error reading response from {location}"))?;
+    Ok(Some(contents))
+}
+
+/// Resolves extensions from package specs in sqlpkg's registry format, and converts
+/// them into a spm.json.
+///
+/// Examples:
+///```
+/// "sqlpkg:nalgeon/uuid"
+/// "sqlpkg:nalgeon/uuid@0.21.6"
+/// "sqlpkg:https://example.com/specs/uuid.json"
+/// ```
+pub struct SqlpkgPackage {
+    /// The reference after `sqlpkg:`, without a version
+    reference: String,
+    /// URL or local path of the package spec
+    spec_location: String,
+    version: Option<String>,
+    prerelease: bool,
+    config: SpmConfig,
+}
+
+impl SqlpkgPackage {
+    pub fn parse(reference: &str, prerelease: bool, config: &SpmConfig) -> Result<SqlpkgPackage> {
+        let (reference, version) = match reference.rsplit_once('@') {
+            Some((reference, version)) if !version.contains('/') => {
+                (reference, Some(version.to_owned()))
+            }
+            _ => (reference, None),
+        };
+        let spec_location = if reference.starts_with("http://")
+            || reference.starts_with("https://")
+            || reference.ends_with(".json")
+        {
+            reference.to_owned()
+        } else {
+            let (owner, name) = reference
+                .split_once('/')
+                .ok_or_else(|| anyhow!("sqlpkg packages look like owner/name: {reference}"))?;
+            let registry = config
+                .sqlpkg
+                .as_ref()
+                .and_then(|sqlpkg| sqlpkg.registry.clone())
+                .or_else(|| std::env::var("SPM_SQLPKG_REGISTRY").ok())
+                .unwrap_or_else(|| DEFAULT_REGISTRY.to_owned());
+            format!("{}/pkg/{owner}/{name}.json", registry.trim_end_matches('/'))
+        };
+        Ok(SqlpkgPackage {
+            reference: reference.to_owned(),
+            spec_location,
+            version,
+            prerelease,
+            config: config.clone(),
+        })
+    }
+
+    fn fetch_spec(&self) -> Result<SqlpkgSpec> {
+        let contents = read_location(&self.spec_location)?;
+        serde_json::from_slice(&contents)
+            .with_context(|| format!("{} is not a valid sqlpkg package spec", self.spec_location))
+    }
+
+    /// The latest version of a spec, from its latest release when it's "latest"
+    fn spec_version(&self, spec: &SqlpkgSpec) -> Result<String> {
+        if !(spec.version.is_empty() || spec.version == "latest") {
+            return Ok(spec.version.clone());
+        }
+        let repository = spec.repository.as_ref().ok_or_else(|| {
+            anyhow!(
+                "{} has no version or repository to find one from",
+                self.spec_location
+            )
+        })?;
+        let tag = determine_package_resolver(repository, self.prerelease, &self.config)?
+            .latest_version()?;
+        // sqlpkg asset paths add their own "v" prefix where needed
+        Ok(tag.trim_start_matches('v').to_owned())
+    }
+}
+
+/// Replace the `{placeholder}`s in an sqlpkg asset path or pattern
+fn expand(template: &str, spec: &SqlpkgSpec, version: &str) -> String {
+    let mut parts = version.trim_start_matches('v').split('.');
+    let (major, minor, patch) = (
+        parts.next().unwrap_or(""),
+        parts.next().unwrap_or(""),
+        parts.next().unwrap_or(""),
+    );
+    template
+        .replace("{repository}", spec.repository.as_deref().unwrap_or(""))
+        .replace("{owner}", &spec.owner)
+        .replace("{name}", &spec.name)
+        .replace("{version}", version)
+        .replace("{major}", major)
+        .replace("{minor}", minor)
+        .replace("{patch}", patch)
+}
+
+impl PackageResolver for SqlpkgPackage {
+    fn version_from_reference(&self) -> Result<String> {
+        match &self.version {
+            Some(v) => Ok(v.to_owned()),
+            None => self.latest_version(),
+        }
+    }
+    fn toml_name(&self) -> String {
+        format!("sqlpkg:{}", self.reference)
+    }
+    fn latest_version(&self) -> Result<String> {
+        self.spec_version(&self.fetch_spec()?)
+    }
+    fn list_versions(&self) -> Result<Vec<PackageVersion>> {
+        // sqlpkg specs only describe their latest version
+        Ok(vec![PackageVersion::new(self.latest_version()?)])
+    }
+    fn generate_lock(&self, definition: &SpmTomlExtensionDefinition) -> Result<SpmLockExtension> {
+        let version = definition.required_version(&self.toml_name())?;
+        let artifacts = definition.artifacts();
+        let spec = self.fetch_spec()?;
+        let path = expand(&spec.assets.path, &spec, &version);
+
+        let mut loadable = vec![];
+        let mut resolved_assets = BTreeMap::new();
+        for (sqlpkg_os, sqlpkg_arch, os, cpu) in PLATFORMS {
+            let asset_name = match (
+                spec.assets.files.get(&format!("{sqlpkg_os}-{sqlpkg_arch}")),
+                &spec.assets.pattern,
+            ) {
+                (Some(file), _) => file.clone(),
+                (None, Some(pattern)) if spec.assets.files.is_empty() => {
+                    expand(pattern, &spec, &version)
+                        .replace("{os}", sqlpkg_os)
+                        .replace("{arch}", sqlpkg_arch)
+                }
+                _ => continue,
+            };
+            let url = format!("{}/{asset_name}", path.trim_end_matches('/'));
+            let asset_sha256 = match spec.assets.checksums.get(&asset_name) {
+                Some(checksum) => checksum
+                    .trim_start_matches("sha256-")
+                    .trim_start_matches("sha256:")
+                    .to_owned(),
+                // patterns don't say which platforms exist, so skip any that don't
+                None if spec.assets.files.is_empty() && !spec.assets.checksums.is_empty() => {
+                    continue
+                }
+                // no published checksum, so trust the asset as it is right now
+                None => match read_asset(&url)? {
+                    Some(contents) => hex::encode(Sha256::digest(contents)),
+                    None if spec.assets.files.is_empty() => continue,
+                    None => return Err(anyhow!("{url} does not exist")),
+                },
+            };
+            resolved_assets.insert(asset_name.clone(), url);
+            loadable.push(SpmPackageJsonPlatform {
+                os: os.to_owned(),
+                cpu: cpu.to_owned(),
+                asset_name,
+                asset_sha256,
+                asset_md5: "".to_owned(),
+            });
+        }
+        if loadable.is_empty() {
+            return Err(anyhow!(
+                "No assets found for {} {version} in {}",
+                self.toml_name(),
+                self.spec_location
+            ));
+        }
+
+        Ok(SpmLockExtension::Https(HttpsExtension {
+            version,
+            artifacts,
+            resolved_spm_json: Some(self.spec_location.clone()),
+            resolved_assets,
+            integrity: "".to_owned(),
+            spm_json: SpmPackageJson {
+                version: 0,
+                description: spec.description,
+                loadable,
+                static_: None,
+            },
+            inferred: true,
+            loadables_only: false,
+            registry: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{lock_and_install, temp_project, zip, TestServer};
+
+    #[test]
+    fn test_local_registry() {
+        let linux = zip(&[("uuid0.so", b"linux")]);
+        let server = TestServer::start(vec![(
+            "/releases/download/0.1.0/uuid-linux-x86.zip".to_owned(),
+            linux.clone(),
+        )]);
+        let project = temp_project("sqlpkg");
+        let spec = serde_json::json!({
+          "owner": "nalgeon",
+          "name": "uuid",
+          "version": "0.1.0",
+          "repository": server.url(""),
+          "description": "UUIDs",
+          "assets": {
+            "path": "{repository}/releases/download/{version}",
+            "files": {
+              "darwin-arm64": "uuid-macos-arm64.zip",
+              "linux-amd64": "uuid-linux-x86.zip"
+            },
+            "checksums": {"uuid-macos-arm64.zip": "sha256-abc"}
+          }
+        });
+        let spec_path = project.resolve_path("pkg/nalgeon/uuid.json");
+        std::fs::create_dir_all(spec_path.parent().unwrap()).unwrap();
+        std::fs::write(&spec_path, spec.to_string()).unwrap();
+
+        let config: SpmConfig = toml::from_str(&format!(
+            "[sqlpkg]\nregistry = '{}'",
+            project.resolve_path("").display()
+        ))
+        .unwrap();
+        let package = SqlpkgPackage::parse("nalgeon/uuid", false, &config).unwrap();
+        assert_eq!(package.toml_name(), "sqlpkg:nalgeon/uuid");
+        assert_eq!(package.latest_version().unwrap(), "0.1.0");
+
+        let (lock, installed) = lock_and_install(&package, "0.1.0", "sqlpkg-install");
+        let extension = match lock {
+            SpmLockExtension::Https(extension) => extension,
+            _ => panic!("expected an HTTPS lock entry"),
+        };
+        assert!(extension.inferred);
+        assert_eq!(extension.spm_json.description, "UUIDs");
+        let loadable = &extension.spm_json.loadable;
+        assert_eq!(
+            (loadable[0].os.as_str(), loadable[0].cpu.as_str()),
+            ("macos", "aarch64")
+        );
+        assert_eq!(loadable[0].asset_sha256, "abc");
+        assert_eq!(
+            loadable[1].asset_sha256,
+            hex::encode(Sha256::digest(&linux))
+        );
+        assert_eq!(
+            extension.resolved_assets.get("uuid-linux-x86.zip"),
+            Some(&server.url("/releases/download/0.1.0/uuid-linux-x86.zip"))
+        );
+        assert_eq!(
+            std::fs::read(installed.resolve_path("sqlite_extensions/uuid0.so")).unwrap(),
+            b"linux"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Helpers for testing resolvers against a local HTTP server.
+use crate::spm::{PackageResolver, Project, SpmLockExtension, SpmTomlExtensionDefinition};
+
+use flate2::{write::GzEncoder, Compression};
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use tiny_http::{Response, Server};
+
+/// A local HTTP server that serves a fixed set of paths until dropped.
+pub struct TestServer {
+    server: std::sync::Arc<Server>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    /// Serve each (path, body) pair, where path includes any query string.
+    /// `$SERVER` in UTF-8 bodies is replaced with the server's base URL.
+    /// Unknown paths respond with a 404.
+    pub fn start(routes: Vec<(String, Vec<u8>)>) -> TestServer {
+        isolate();
+        let server = std::sync::Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let base_url = format!("http://{}", server.server_addr());
+        let routes: Vec<(String, Vec<u8>)> = routes
+            .into_iter()
+            .map(|(path, body)| match String::from_utf8(body) {
+                Ok(body) => (path, body.replace("$SERVER", &base_url).into_bytes()),
+                Err(err) => (path, err.into_bytes()),
+            })
+            .collect();
+        let handle = {
+            let server = server.clone();
+            std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let response = match routes.iter().find(|(path, _)| path == request.url()) {
+                        Some((_, body)) => Response::from_data(body.clone()),
+                        None => Response::from_data(b"not found".to_vec()).with_status_code(404),
+                    };
+                    let _ = request.respond(response);
+                }
+            })
+        };
+        TestServer {
+            server,
+            handle: Some(handle),
+        }
+    }
+
+    /// Full URL to the given path on this server
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.server.server_addr())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Build a .tar.gz archive with the given (name, contents) entries
+pub fn tar_gz(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (name, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, *contents).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+/// Build a .zip archive with the given (name, contents) entries
+pub fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, contents) in files {
+        writer
+            .start_file(*name, zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(contents).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// Keep the developer's environment out of tests: no user config, AWS files, tokens or
+/// index overrides, and the HTTP client's globals at their defaults. Runs once per process.
+pub fn isolate() {
+    static ISOLATE: std::sync::Once = std::sync::Once::new();
+    ISOLATE.call_once(|| {
+        let missing = std::env::temp_dir().join("spm-test-missing");
+        std::env::set_var("SPM_CONFIG", missing.join("config.toml"));
+        std::env::set_var("AWS_CONFIG_FILE", missing.join("aws-config"));
+        std::env::set_var(
+            "AWS_SHARED_CREDENTIALS_FILE",
+            missing.join("aws-credentials"),
+        );
+        for name in [
+            "GH_TOKEN",
+            "GITHUB_TOKEN",
+            "SPM_GITHUB_URL",
+            "SPM_GITHUB_API_URL",
+            "SPM_INDEX",
+            "SPM_NPM_REGISTRY",
+            "SPM_PYPI_URL",
+            "SPM_SQLPKG_REGISTRY",
+            "AWS_ACCESS_KEY_ID",
+            "AWS_SECRET_ACCESS_KEY",
+            "AWS_SESSION_TOKEN",
+            "AWS_PROFILE",
+            "AWS_REGION",
+            "AWS_DEFAULT_REGION",
+            "AWS_ENDPOINT_URL",
+            "AWS_ENDPOINT_URL_S3",
+        ] {
+            std::env::remove_var(name);
+        }
+        crate::http::set_offline(false);
+        crate::http::set_tokens(vec![]);
+        crate::http::set_mirrors(vec![]);
+    });
+}
+
+/// A fresh project in a temporary directory, with an empty sqlite_extensions/
+pub fn temp_project(name: &str) -> Project {
+    isolate();
+    let directory: PathBuf = std::env::temp_dir().join(format!(
+        "spm-test-{name}-{}-{:?}",
+        std::process::id(),
+        std::thread::current().id()
+    ));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(directory.join("sqlite_extensions")).unwrap();
+    Project::new(directory)
+}
+
+/// Lock `version` of a package, round-trip the entry through spm.lock's JSON, then
+/// install it for linux x86_64 into a fresh project, as `spm add` and `spm install` would
+pub fn lock_and_install(
+    resolver: &dyn PackageResolver,
+    version: &str,
+    name: &str,
+) -> (SpmLockExtension, Project) {
+    let lock = resolver
+        .generate_lock(&SpmTomlExtensionDefinition::Version(version.to_owned()))
+        .unwrap();
+    let serialized = serde_json::to_string(&lock).unwrap();
+    assert_eq!(
+        serde_json::from_str::<SpmLockExtension>(&serialized).unwrap(),
+        lock
+    );
+    let project = temp_project(name);
+    lock.install_platform(Some(("linux".to_owned(), "x86_64".to_owned())), &project)
+        .unwrap();
+    (lock, project)
+}